@@ -0,0 +1,259 @@
+//! Compressed `IDX` files: transparent decompression of files recognized by their magic bytes,
+//! and writing compressed files.
+
+#[cfg(feature = "std")]
+use crate::read::read_fully;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::DataFormat;
+use crate::Error;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::ErrorKind;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use crate::IdxArray;
+use alloc::borrow::Cow;
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "std")]
+use std::fmt;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "zstd")]
+use std::io::BufReader;
+#[cfg(feature = "std")]
+use std::io::Chain;
+#[cfg(feature = "std")]
+use std::io::Cursor;
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+use std::io::Write;
+
+/// The first bytes of a gzip stream.
+#[cfg(any(feature = "gzip", feature = "checksum"))]
+pub(crate) const GZIP_MAGIC: [u8; 2] = [0x1F, 0x8B];
+
+/// The first bytes of a zstd frame.
+#[cfg(feature = "zstd")]
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+/// The longest magic number of any supported compression format.
+#[cfg(feature = "std")]
+const MAGIC_LEN: usize = 4;
+
+/// A stream with the bytes that were read to recognize its format put back in front.
+#[cfg(feature = "std")]
+type Peeked<R> = Chain<Cursor<Vec<u8>>, R>;
+
+/// Decompresses a stream if it starts with the magic bytes of a supported compression format,
+/// and passes it through unchanged otherwise.
+#[cfg(feature = "std")]
+pub(crate) enum Decoder<R> {
+    Plain(Peeked<R>),
+    #[cfg(feature = "gzip")]
+    Gzip(GzDecoder<Peeked<R>>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Decoder<'static, BufReader<Peeked<R>>>),
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Decoder<R> {
+    pub(crate) fn new(mut reader: R) -> io::Result<Self> {
+        let mut magic = vec![0; MAGIC_LEN];
+        let found = read_fully(&mut reader, &mut magic)?;
+        magic.truncate(found);
+        #[cfg(feature = "gzip")]
+        if magic.starts_with(&GZIP_MAGIC) {
+            return Ok(Decoder::Gzip(GzDecoder::new(
+                Cursor::new(magic).chain(reader),
+            )));
+        }
+        #[cfg(feature = "zstd")]
+        if magic.starts_with(&ZSTD_MAGIC) {
+            return Ok(Decoder::Zstd(zstd::Decoder::new(
+                Cursor::new(magic).chain(reader),
+            )?));
+        }
+        Ok(Decoder::Plain(Cursor::new(magic).chain(reader)))
+    }
+
+    pub(crate) fn into_inner(self) -> R {
+        match self {
+            Decoder::Plain(reader) => reader.into_inner().1,
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(reader) => reader.into_inner().into_inner().1,
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(reader) => reader.finish().into_inner().into_inner().1,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Plain(reader) => reader.read(buf),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(reader) => reader.read(buf),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(reader) => reader.read(buf),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<R> fmt::Debug for Decoder<R> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Decoder::Plain(_) => f.write_str("Plain"),
+            #[cfg(feature = "gzip")]
+            Decoder::Gzip(_) => f.write_str("Gzip"),
+            #[cfg(feature = "zstd")]
+            Decoder::Zstd(_) => f.write_str("Zstd"),
+        }
+    }
+}
+
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn decompression_error(reason: String) -> Error {
+    Error {
+        offset: 0,
+        kind: ErrorKind::Decompression { reason },
+    }
+}
+
+/// Reads all of `decoder`, failing if there is more than `max_len` bytes of it.
+#[cfg(any(feature = "gzip", feature = "zstd"))]
+fn decompress_all(decoder: impl Read, max_len: Option<usize>) -> Result<Vec<u8>, Error> {
+    let limit = max_len.map_or(u64::MAX, |max_len| (max_len as u64).saturating_add(1));
+    let mut output = Vec::new();
+    decoder
+        .take(limit)
+        .read_to_end(&mut output)
+        .map_err(|e| decompression_error(e.to_string()))?;
+    match max_len {
+        Some(max_len) if output.len() > max_len => Err(decompression_error(format!(
+            "decompressed input is longer than the limit of {max_len} bytes"
+        ))),
+        _ => Ok(output),
+    }
+}
+
+/// Decompresses `input` if it starts with the magic bytes of a supported compression format,
+/// and borrows it unchanged otherwise. Decompression stops with an error once the output is
+/// longer than `max_len`, if given.
+#[cfg_attr(not(any(feature = "gzip", feature = "zstd")), allow(unused_variables))]
+pub(crate) fn decompress(input: &[u8], max_len: Option<usize>) -> Result<Cow<'_, [u8]>, Error> {
+    #[cfg(feature = "gzip")]
+    if input.starts_with(&GZIP_MAGIC) {
+        let output = decompress_all(GzDecoder::new(input), max_len)?;
+        return Ok(Cow::Owned(output));
+    }
+    #[cfg(feature = "zstd")]
+    if input.starts_with(&ZSTD_MAGIC) {
+        let decoder = zstd::Decoder::new(input).map_err(|e| decompression_error(e.to_string()))?;
+        let output = decompress_all(decoder, max_len)?;
+        return Ok(Cow::Owned(output));
+    }
+    Ok(Cow::Borrowed(input))
+}
+
+#[cfg(feature = "gzip")]
+impl<T: DataFormat, const N: usize> IdxArray<T, N> {
+    /// Writes the array to `writer` as a gzipped `IDX` file, like the `.gz` files MNIST is
+    /// distributed as, returning `writer` once the stream is complete.
+    pub fn write_gzip_to<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut encoder = GzEncoder::new(writer, flate2::Compression::default());
+        self.write_to(&mut encoder)?;
+        encoder.finish()
+    }
+}
+
+#[cfg(feature = "zstd")]
+impl<T: DataFormat, const N: usize> IdxArray<T, N> {
+    /// Writes the array to `writer` as a zstd-compressed `IDX` file at the given compression
+    /// `level`, returning `writer` once the frame is complete.
+    ///
+    /// A `level` of `0` uses zstd's default.
+    pub fn write_zstd_to<W: Write>(&self, writer: W, level: i32) -> io::Result<W> {
+        let mut encoder = zstd::Encoder::new(writer, level)?;
+        self.write_to(&mut encoder)?;
+        encoder.finish()
+    }
+}
+
+#[cfg(all(test, any(feature = "gzip", feature = "zstd")))]
+mod tests {
+    use crate::IdxArray;
+    use std::fs;
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_gzip() {
+        use crate::parse_any;
+        use crate::ErrorKind;
+        use crate::IdxHeader;
+        use crate::IdxRecordReader;
+        use crate::IdxWriter;
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let array = IdxArray::<u8, 1>::parse(&x).expect("parse index");
+        let gz = array.write_gzip_to(Vec::new()).expect("compress");
+        assert_eq!(gz[..2], [0x1F, 0x8B]);
+        assert_eq!(IdxArray::<u8, 1>::parse(&gz).as_ref(), Ok(&array));
+        assert_eq!(parse_any(&gz), Ok(array.clone().into()));
+        assert_eq!(
+            IdxArray::<u8, 1>::read_from(&gz[..]).expect("read index"),
+            array
+        );
+        let header = IdxHeader::read_from(&gz[..]).expect("read header");
+        assert_eq!(header.dims(), [10_000]);
+        let records = IdxRecordReader::<u8, _>::new(&gz[..]).expect("read header");
+        let labels: Vec<_> = records.map(|r| r.expect("read record")[0]).collect();
+        assert_eq!(labels, array.data());
+
+        let e = IdxArray::<u8, 1>::parse(&gz[..gz.len() / 2]).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::Decompression { .. }));
+
+        let encoder = GzEncoder::new(Vec::new(), Compression::default());
+        let mut writer = IdxWriter::<_, u8, 1>::with_len(encoder, 10_000, &[]).expect("header");
+        for &label in array.data() {
+            writer.write_record(&[label]).expect("write record");
+        }
+        let gz = writer.finish().and_then(|e| e.finish()).expect("compress");
+        assert_eq!(gz[..2], [0x1F, 0x8B]);
+        assert_eq!(IdxArray::<u8, 1>::parse(&gz).as_ref(), Ok(&array));
+        assert_eq!(
+            IdxArray::<u8, 1>::read_from(&gz[..]).expect("read index"),
+            array
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd() {
+        use crate::IdxWriter;
+
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let array = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        let zst = array.write_zstd_to(Vec::new(), 0).expect("compress");
+        assert_eq!(IdxArray::<u8, 3>::parse(&zst).as_ref(), Ok(&array));
+        assert_eq!(
+            IdxArray::<u8, 3>::read_from(&zst[..]).expect("read index"),
+            array
+        );
+
+        let encoder = zstd::Encoder::new(Vec::new(), 0).expect("compress");
+        let mut writer =
+            IdxWriter::<_, u8, 3>::with_len(encoder, 10_000, &[28, 28]).expect("header");
+        let (_, data) = array.clone().dims_data();
+        for record in data.chunks_exact(28 * 28) {
+            writer.write_record(record).expect("write record");
+        }
+        let zst = writer.finish().and_then(|e| e.finish()).expect("compress");
+        assert_eq!(IdxArray::<u8, 3>::parse(&zst), Ok(array));
+    }
+}
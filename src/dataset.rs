@@ -0,0 +1,1704 @@
+//! Loaders for well-known datasets distributed as `IDX` files.
+
+use crate::checked_elements;
+#[cfg(feature = "checksum")]
+use crate::compress::decompress;
+#[cfg(feature = "checksum")]
+use crate::compress::GZIP_MAGIC;
+use crate::DataFormat;
+use crate::IdxArray;
+use crate::ReadError;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Error from loading a dataset.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum DatasetError {
+    /// One of the dataset's files could not be read.
+    Read { path: PathBuf, source: ReadError },
+    /// The number of samples and the number of labels differ.
+    LengthMismatch { samples: usize, labels: usize },
+    /// A label is not one of the dataset's classes.
+    InvalidLabel { index: usize, label: i32 },
+    /// An array has the wrong dimensions for the dataset.
+    DimsMismatch { expected: Vec<u32>, found: Vec<u32> },
+    /// The samples have no first axis to split along, or each sample is too large to address.
+    SampleDims { dims: Vec<u32> },
+    /// One of the dataset's files does not have the digest of a known-good copy.
+    ChecksumMismatch {
+        path: PathBuf,
+        expected: Digest,
+        found: Digest,
+    },
+    /// One of the dataset's files was to be verified, but no digest is known for it in the
+    /// form it was found in.
+    MissingDigest { path: PathBuf },
+    /// No dataset of the given name is registered in a [`DatasetRegistry`].
+    UnknownDataset { name: String },
+}
+
+impl fmt::Display for DatasetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatasetError::Read { path, source } => {
+                write!(f, "failed to read {}: {source}", path.display())
+            }
+            DatasetError::LengthMismatch { samples, labels } => {
+                write!(f, "dataset has {samples} samples but {labels} labels")
+            }
+            DatasetError::InvalidLabel { index, label } => {
+                write!(f, "label {label} at index {index} is not a valid class")
+            }
+            DatasetError::DimsMismatch { expected, found } => {
+                write!(f, "expected dimensions {expected:?}, found {found:?}")
+            }
+            DatasetError::SampleDims { dims } => {
+                write!(
+                    f,
+                    "dimensions {dims:?} cannot be split into addressable samples"
+                )
+            }
+            DatasetError::ChecksumMismatch {
+                path,
+                expected,
+                found,
+            } => {
+                let path = path.display();
+                write!(f, "{path} has digest {found}, expected {expected}")
+            }
+            DatasetError::MissingDigest { path } => {
+                write!(f, "no known digest to verify {} against", path.display())
+            }
+            DatasetError::UnknownDataset { name } => write!(f, "no dataset called {name:?}"),
+        }
+    }
+}
+
+impl error::Error for DatasetError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            DatasetError::Read { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// File names of the images and labels of the training split of MNIST and its drop-in
+/// replacements.
+const TRAIN_FILES: [&str; 2] = ["train-images.idx3-ubyte", "train-labels.idx1-ubyte"];
+
+/// File names of the images and labels of the test split of MNIST and its drop-in
+/// replacements.
+const TEST_FILES: [&str; 2] = ["t10k-images.idx3-ubyte", "t10k-labels.idx1-ubyte"];
+
+/// Finds the file called `name` in `dir`, also trying it as spelled in the official
+/// distribution and, with the `gzip` feature, gzipped. Falls back to `name` itself if none of
+/// these exist, so that the error names it.
+fn find_file(dir: &Path, name: &str) -> PathBuf {
+    let official = name.replacen('.', "-", 1);
+    let mut candidates = vec![dir.join(name), dir.join(&official)];
+    if cfg!(feature = "gzip") {
+        candidates.push(dir.join(format!("{name}.gz")));
+        candidates.push(dir.join(format!("{official}.gz")));
+    }
+    let first = candidates[0].clone();
+    candidates
+        .into_iter()
+        .find(|path| path.exists())
+        .unwrap_or(first)
+}
+
+fn read_file<T: DataFormat, const N: usize>(path: PathBuf) -> Result<IdxArray<T, N>, DatasetError> {
+    IdxArray::from_path(&path).map_err(|source| DatasetError::Read { path, source })
+}
+
+/// Samples along the first axis of an `IDX` array, each paired with a label.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct LabeledIdx<X, Y, const N: usize> {
+    samples: IdxArray<X, N>,
+    labels: Vec<Y>,
+    sample_len: usize,
+}
+
+impl<X, Y, const N: usize> LabeledIdx<X, Y, N> {
+    /// Pairs up each sample along the first axis of `samples` with an element of `labels`,
+    /// checking that there are as many of one as of the other.
+    ///
+    /// Fails if `N` is `0`, so that there is no first axis, or if each sample has too many
+    /// elements to address, which an array without samples can.
+    pub fn new(samples: IdxArray<X, N>, labels: IdxArray<Y, 1>) -> Result<Self, DatasetError> {
+        let dims = samples.dims();
+        let sample_dims_error = || DatasetError::SampleDims {
+            dims: dims.to_vec(),
+        };
+        let (&num_samples, sample_dims) = dims.split_first().ok_or_else(sample_dims_error)?;
+        let sample_len =
+            checked_elements(sample_dims, mem::size_of::<X>()).ok_or_else(sample_dims_error)?;
+        let labels = labels.into_sequence();
+        if num_samples as usize != labels.len() {
+            return Err(DatasetError::LengthMismatch {
+                samples: num_samples as usize,
+                labels: labels.len(),
+            });
+        }
+        Ok(LabeledIdx {
+            samples,
+            labels,
+            sample_len,
+        })
+    }
+
+    /// Returns all of the samples, as a single array.
+    pub fn samples(&self) -> &IdxArray<X, N> {
+        &self.samples
+    }
+
+    /// Returns the label of each sample.
+    pub fn labels(&self) -> &[Y] {
+        &self.labels
+    }
+
+    /// Returns the dimensions of each sample, which are those of the samples minus the first.
+    pub fn sample_dims(&self) -> &[u32] {
+        &self.samples.dims[1..]
+    }
+
+    /// Returns the number of samples.
+    pub fn len(&self) -> usize {
+        self.labels.len()
+    }
+
+    /// Returns `true` if there are no samples.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+    }
+
+    /// Returns the flattened sample at `index` and its label, if `index` is in bounds.
+    pub fn get(&self, index: usize) -> Option<(&[X], &Y)> {
+        let label = self.labels.get(index)?;
+        let start = index * self.sample_len;
+        Some((&self.samples.data[start..start + self.sample_len], label))
+    }
+
+    /// Returns each flattened sample with its label, in order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = (&[X], &Y)> {
+        (0..self.len()).map(|index| self.get(index).expect("index in bounds"))
+    }
+
+    /// Returns the samples and the labels.
+    pub fn into_parts(self) -> (IdxArray<X, N>, Vec<Y>) {
+        (self.samples, self.labels)
+    }
+
+    /// Converts each label with `f`.
+    fn map_labels<Z>(self, f: impl FnMut(Y) -> Z) -> LabeledIdx<X, Z, N> {
+        LabeledIdx {
+            samples: self.samples,
+            labels: self.labels.into_iter().map(f).collect(),
+            sample_len: self.sample_len,
+        }
+    }
+}
+
+/// One split of a dataset laid out like MNIST: a sequence of 28x28 images, and the label of
+/// each, decoded as `L`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ImageSplit<L> {
+    labeled: LabeledIdx<u8, L, 3>,
+}
+
+impl<L> ImageSplit<L> {
+    /// Reads the images and labels from the `IDX` files at the given paths, and pairs them up
+    /// with `new`.
+    fn read<Y: DataFormat, const N: usize>(
+        images: &Path,
+        labels: &Path,
+        new: fn(IdxArray<u8, 3>, IdxArray<Y, N>) -> Result<Self, DatasetError>,
+    ) -> Result<Self, DatasetError> {
+        new(
+            read_file(images.to_path_buf())?,
+            read_file(labels.to_path_buf())?,
+        )
+    }
+
+    /// Returns the images, with dimensions `[len, 28, 28]`.
+    pub fn images(&self) -> &IdxArray<u8, 3> {
+        self.labeled.samples()
+    }
+
+    /// Returns the label of each image.
+    pub fn labels(&self) -> &[L] {
+        self.labeled.labels()
+    }
+
+    /// Returns the number of images.
+    pub fn len(&self) -> usize {
+        self.labeled.len()
+    }
+
+    /// Returns `true` if there are no images.
+    pub fn is_empty(&self) -> bool {
+        self.labeled.is_empty()
+    }
+
+    /// Returns the images paired with their labels.
+    pub fn as_labeled(&self) -> &LabeledIdx<u8, L, 3> {
+        &self.labeled
+    }
+}
+
+/// One split of the MNIST handwritten digit dataset, labeled with the digit each image shows.
+pub type MnistSplit = ImageSplit<u8>;
+
+impl MnistSplit {
+    /// Pairs up `images` with `labels`, checking that there is one label per image and that
+    /// each label is a digit.
+    pub fn new(images: IdxArray<u8, 3>, labels: IdxArray<u8, 1>) -> Result<Self, DatasetError> {
+        let labeled = LabeledIdx::new(images, labels)?;
+        let mut labels = labeled.labels().iter().enumerate();
+        if let Some((index, &label)) = labels.find(|(_, &label)| label > 9) {
+            let label = label.into();
+            return Err(DatasetError::InvalidLabel { index, label });
+        }
+        Ok(ImageSplit { labeled })
+    }
+
+    /// Reads the images and labels from the `IDX` files at the given paths.
+    pub fn from_paths(
+        images: impl AsRef<Path>,
+        labels: impl AsRef<Path>,
+    ) -> Result<Self, DatasetError> {
+        Self::read(images.as_ref(), labels.as_ref(), Self::new)
+    }
+}
+
+/// The MNIST handwritten digit dataset, as described in <http://yann.lecun.com/exdb/mnist/>.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Mnist {
+    train: MnistSplit,
+    test: MnistSplit,
+}
+
+impl Mnist {
+    /// Loads both splits from `dir`, which holds the four files under their usual names, as
+    /// [`DatasetSpec::MNIST`] describes them.
+    ///
+    /// The files may be named as in this crate's `data` directory (`train-images.idx3-ubyte`)
+    /// or as in the official distribution (`train-images-idx3-ubyte`), and with the `gzip`
+    /// feature may also be gzipped with a `.gz` extension.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let dir = dir.as_ref();
+        let spec = DatasetSpec::MNIST;
+        let split = |split| {
+            let labeled = spec.load_split(dir, split, None)?;
+            Ok(ImageSplit { labeled })
+        };
+        Ok(Mnist {
+            train: split(&spec.train)?,
+            test: split(&spec.test)?,
+        })
+    }
+
+    /// Returns the 60,000 training images.
+    pub fn train(&self) -> &MnistSplit {
+        &self.train
+    }
+
+    /// Returns the 10,000 test images.
+    pub fn test(&self) -> &MnistSplit {
+        &self.test
+    }
+}
+
+/// The names of the classes of Fashion-MNIST, in the order of their labels.
+const FASHION_CLASSES: &[&str] = &[
+    "T-shirt/top",
+    "Trouser",
+    "Pullover",
+    "Dress",
+    "Coat",
+    "Sandal",
+    "Shirt",
+    "Sneaker",
+    "Bag",
+    "Ankle boot",
+];
+
+/// The classes of clothing in the Fashion-MNIST dataset, numbered as in its label files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum FashionLabel {
+    TShirt = 0,
+    Trouser = 1,
+    Pullover = 2,
+    Dress = 3,
+    Coat = 4,
+    Sandal = 5,
+    Shirt = 6,
+    Sneaker = 7,
+    Bag = 8,
+    AnkleBoot = 9,
+}
+
+impl FashionLabel {
+    /// Every class, in the order of their raw labels.
+    pub const ALL: [FashionLabel; 10] = [
+        FashionLabel::TShirt,
+        FashionLabel::Trouser,
+        FashionLabel::Pullover,
+        FashionLabel::Dress,
+        FashionLabel::Coat,
+        FashionLabel::Sandal,
+        FashionLabel::Shirt,
+        FashionLabel::Sneaker,
+        FashionLabel::Bag,
+        FashionLabel::AnkleBoot,
+    ];
+
+    /// Returns the name of the class, as given by the dataset's authors.
+    pub fn name(self) -> &'static str {
+        FASHION_CLASSES[self as usize]
+    }
+}
+
+impl fmt::Display for FashionLabel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Fails with the raw label if it is not one of the ten classes.
+impl TryFrom<u8> for FashionLabel {
+    type Error = u8;
+
+    fn try_from(label: u8) -> Result<Self, u8> {
+        FashionLabel::ALL
+            .get(usize::from(label))
+            .copied()
+            .ok_or(label)
+    }
+}
+
+impl From<FashionLabel> for u8 {
+    fn from(label: FashionLabel) -> Self {
+        label as u8
+    }
+}
+
+/// One split of the Fashion-MNIST dataset, labeled with the class of clothing each image shows.
+pub type FashionMnistSplit = ImageSplit<FashionLabel>;
+
+impl FashionMnistSplit {
+    /// Pairs up `images` with `labels`, checking that there is one label per image and that
+    /// each label is one of the ten classes.
+    pub fn new(images: IdxArray<u8, 3>, labels: IdxArray<u8, 1>) -> Result<Self, DatasetError> {
+        let (dims, labels) = labels.dims_data();
+        let labels = labels
+            .into_iter()
+            .enumerate()
+            .map(|(index, label)| {
+                FashionLabel::try_from(label).map_err(|label| DatasetError::InvalidLabel {
+                    index,
+                    label: label.into(),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        let labels = IdxArray { dims, data: labels };
+        let labeled = LabeledIdx::new(images, labels)?;
+        Ok(ImageSplit { labeled })
+    }
+
+    /// Reads the images and labels from the `IDX` files at the given paths.
+    pub fn from_paths(
+        images: impl AsRef<Path>,
+        labels: impl AsRef<Path>,
+    ) -> Result<Self, DatasetError> {
+        Self::read(images.as_ref(), labels.as_ref(), Self::new)
+    }
+}
+
+/// The Fashion-MNIST dataset of Zalando's article images, a drop-in replacement for MNIST, as
+/// described in <https://github.com/zalandoresearch/fashion-mnist>.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FashionMnist {
+    train: FashionMnistSplit,
+    test: FashionMnistSplit,
+}
+
+impl FashionMnist {
+    /// Loads both splits from `dir`, which holds the four files under the same names as for
+    /// [`Mnist::from_dir`], as [`DatasetSpec::FASHION_MNIST`] describes them.
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let dir = dir.as_ref();
+        let spec = DatasetSpec::FASHION_MNIST;
+        let split = |split| {
+            let labeled = spec.load_split(dir, split, None)?;
+            let labeled = labeled.map_labels(|label| {
+                FashionLabel::try_from(label).expect("label checked against the classes")
+            });
+            Ok(ImageSplit { labeled })
+        };
+        Ok(FashionMnist {
+            train: split(&spec.train)?,
+            test: split(&spec.test)?,
+        })
+    }
+
+    /// Returns the 60,000 training images.
+    pub fn train(&self) -> &FashionMnistSplit {
+        &self.train
+    }
+
+    /// Returns the 10,000 test images.
+    pub fn test(&self) -> &FashionMnistSplit {
+        &self.test
+    }
+}
+
+/// The variants of the EMNIST dataset, which its authors call splits, each with its own set
+/// of classes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum EmnistSplit {
+    /// Digits, uppercase and lowercase letters, in 62 unbalanced classes.
+    ByClass,
+    /// Digits and letters in 47 unbalanced classes, with the lowercase letters that look like
+    /// their uppercase counterparts merged into them.
+    ByMerge,
+    /// The same classes as [`EmnistSplit::ByMerge`], with the same number of images in each.
+    Balanced,
+    /// Letters with uppercase and lowercase merged, in 26 balanced classes numbered from `1`.
+    Letters,
+    /// Digits, in 10 balanced classes.
+    Digits,
+    /// Digits, in 10 balanced classes, with as many images as MNIST.
+    Mnist,
+}
+
+/// The classes of [`EmnistSplit::ByClass`], in the order of their labels.
+const EMNIST_BYCLASS: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+
+/// The classes of [`EmnistSplit::ByMerge`], which keeps apart only those lowercase letters that
+/// look different from their uppercase counterparts.
+const EMNIST_BYMERGE: &[u8] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabdefghnqrt";
+
+impl EmnistSplit {
+    /// Every split.
+    pub const ALL: [EmnistSplit; 6] = [
+        EmnistSplit::ByClass,
+        EmnistSplit::ByMerge,
+        EmnistSplit::Balanced,
+        EmnistSplit::Letters,
+        EmnistSplit::Digits,
+        EmnistSplit::Mnist,
+    ];
+
+    /// Returns the name of the split, as used in its file names.
+    pub fn name(self) -> &'static str {
+        match self {
+            EmnistSplit::ByClass => "byclass",
+            EmnistSplit::ByMerge => "bymerge",
+            EmnistSplit::Balanced => "balanced",
+            EmnistSplit::Letters => "letters",
+            EmnistSplit::Digits => "digits",
+            EmnistSplit::Mnist => "mnist",
+        }
+    }
+
+    /// Returns the characters that the split's classes stand for, and the label of the first.
+    fn classes(self) -> (&'static [u8], usize) {
+        match self {
+            EmnistSplit::ByClass => (EMNIST_BYCLASS, 0),
+            EmnistSplit::ByMerge | EmnistSplit::Balanced => (EMNIST_BYMERGE, 0),
+            EmnistSplit::Letters => (&EMNIST_BYCLASS[10..36], 1),
+            EmnistSplit::Digits | EmnistSplit::Mnist => (&EMNIST_BYCLASS[..10], 0),
+        }
+    }
+
+    /// Returns the number of classes in the split.
+    pub fn num_classes(self) -> usize {
+        self.classes().0.len()
+    }
+
+    /// Returns the character that `label` stands for in this split, or `None` if it is not one
+    /// of the split's classes. The merged letters of [`EmnistSplit::Letters`] are given in
+    /// uppercase.
+    pub fn label_char(self, label: u8) -> Option<char> {
+        let (classes, first) = self.classes();
+        let c = classes.get(usize::from(label).checked_sub(first)?)?;
+        Some(char::from(*c))
+    }
+}
+
+impl fmt::Display for EmnistSplit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Transposes each image of `images`, which EMNIST stores with rows and columns swapped
+/// relative to MNIST.
+fn transpose_images(images: IdxArray<u8, 3>) -> IdxArray<u8, 3> {
+    let ([len, height, width], data) = images.dims_data();
+    let (height, width) = (height as usize, width as usize);
+    if height * width == 0 {
+        let dims = [len, width as u32, height as u32];
+        return IdxArray { dims, data };
+    }
+    let mut transposed = Vec::with_capacity(data.len());
+    for image in data.chunks_exact(height * width) {
+        for col in 0..width {
+            transposed.extend((0..height).map(|row| image[row * width + col]));
+        }
+    }
+    IdxArray {
+        dims: [len, width as u32, height as u32],
+        data: transposed,
+    }
+}
+
+/// The EMNIST dataset of handwritten characters, as described in
+/// <https://www.nist.gov/itl/products-and-services/emnist-dataset>.
+///
+/// The images are transposed as they are loaded, so that they have the same orientation as
+/// those of MNIST.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Emnist {
+    split: EmnistSplit,
+    train: ImageSplit<u8>,
+    test: ImageSplit<u8>,
+}
+
+impl Emnist {
+    /// Loads both halves of `split` from `dir`, which holds its four files under their usual
+    /// names, such as `emnist-balanced-train-images-idx3-ubyte`, found as for
+    /// [`Mnist::from_dir`].
+    pub fn from_dir(dir: impl AsRef<Path>, split: EmnistSplit) -> Result<Self, DatasetError> {
+        let dir = dir.as_ref();
+        let load = |part: &str| {
+            let name = |file: &str| find_file(dir, &format!("emnist-{split}-{part}-{file}"));
+            let images = read_file(name("images.idx3-ubyte"))?;
+            let labels = read_file(name("labels.idx1-ubyte"))?;
+            Self::labeled(split, images, labels)
+        };
+        Ok(Emnist {
+            split,
+            train: load("train")?,
+            test: load("test")?,
+        })
+    }
+
+    /// Pairs up `images` as stored in an EMNIST file with `labels`, checking that each label is
+    /// one of the classes of `split`.
+    fn labeled(
+        split: EmnistSplit,
+        images: IdxArray<u8, 3>,
+        labels: IdxArray<u8, 1>,
+    ) -> Result<ImageSplit<u8>, DatasetError> {
+        let labeled = LabeledIdx::new(transpose_images(images), labels)?;
+        let mut labels = labeled.labels().iter().enumerate();
+        if let Some((index, &label)) = labels.find(|(_, &l)| split.label_char(l).is_none()) {
+            let label = label.into();
+            return Err(DatasetError::InvalidLabel { index, label });
+        }
+        Ok(ImageSplit { labeled })
+    }
+
+    /// Returns which split this is.
+    pub fn split(&self) -> EmnistSplit {
+        self.split
+    }
+
+    /// Returns the training images with their labels.
+    pub fn train(&self) -> &ImageSplit<u8> {
+        &self.train
+    }
+
+    /// Returns the test images with their labels.
+    pub fn test(&self) -> &ImageSplit<u8> {
+        &self.test
+    }
+
+    /// Returns the character that `label` stands for, as for [`EmnistSplit::label_char`].
+    pub fn label_char(&self, label: u8) -> Option<char> {
+        self.split.label_char(label)
+    }
+}
+
+/// The extended label of a QMNIST image, one row of its `i32` label file, which traces the
+/// image back to where it came from in NIST Special Database 19.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct QmnistLabel {
+    /// The digit shown by the image.
+    pub digit: u8,
+    /// The NIST HSF series that the image comes from: `0`, `1` or `4`.
+    pub hsf_series: i32,
+    /// The NIST id of the person who wrote the digit.
+    pub writer_id: i32,
+    /// The index of the digit among those written by the same person.
+    pub writer_digit_index: i32,
+    /// The NIST class code of the character, which is its ASCII code.
+    pub nist_class_code: i32,
+    /// The index of the digit among all of those in NIST.
+    pub nist_index: i32,
+    /// Nonzero if the image duplicates another.
+    pub duplicate: i32,
+    /// Reserved, and `0` in the released files.
+    pub unused: i32,
+}
+
+impl QmnistLabel {
+    /// The number of columns of a QMNIST label file.
+    pub const COLUMNS: usize = 8;
+
+    /// Reads a label from a row of a QMNIST label file. Fails with the raw class if it is not a
+    /// digit.
+    pub fn from_row(row: [i32; QmnistLabel::COLUMNS]) -> Result<Self, i32> {
+        let class = row[0];
+        let digit = u8::try_from(class)
+            .ok()
+            .filter(|&digit| digit <= 9)
+            .ok_or(class)?;
+        Ok(QmnistLabel {
+            digit,
+            hsf_series: row[1],
+            writer_id: row[2],
+            writer_digit_index: row[3],
+            nist_class_code: row[4],
+            nist_index: row[5],
+            duplicate: row[6],
+            unused: row[7],
+        })
+    }
+
+    /// Returns the label as a row of a QMNIST label file.
+    pub fn to_row(self) -> [i32; QmnistLabel::COLUMNS] {
+        [
+            self.digit.into(),
+            self.hsf_series,
+            self.writer_id,
+            self.writer_digit_index,
+            self.nist_class_code,
+            self.nist_index,
+            self.duplicate,
+            self.unused,
+        ]
+    }
+}
+
+/// One split of the QMNIST dataset, labeled with the extended label of each image.
+pub type QmnistSplit = ImageSplit<QmnistLabel>;
+
+impl QmnistSplit {
+    /// Pairs up `images` with the rows of `labels`, checking that there is one row per image,
+    /// that each row has [`QmnistLabel::COLUMNS`] columns and that each class is a digit.
+    pub fn new(images: IdxArray<u8, 3>, labels: IdxArray<i32, 2>) -> Result<Self, DatasetError> {
+        let ([len, columns], table) = labels.dims_data();
+        if columns as usize != QmnistLabel::COLUMNS {
+            return Err(DatasetError::DimsMismatch {
+                expected: vec![len, QmnistLabel::COLUMNS as u32],
+                found: vec![len, columns],
+            });
+        }
+        let labels = table
+            .chunks_exact(QmnistLabel::COLUMNS)
+            .enumerate()
+            .map(|(index, row)| {
+                let row = row.try_into().expect("row of a label file");
+                QmnistLabel::from_row(row)
+                    .map_err(|label| DatasetError::InvalidLabel { index, label })
+            })
+            .collect::<Result<_, _>>()?;
+        let labels = IdxArray {
+            dims: [len],
+            data: labels,
+        };
+        let labeled = LabeledIdx::new(images, labels)?;
+        Ok(ImageSplit { labeled })
+    }
+
+    /// Reads the images and labels from the `IDX` files at the given paths.
+    pub fn from_paths(
+        images: impl AsRef<Path>,
+        labels: impl AsRef<Path>,
+    ) -> Result<Self, DatasetError> {
+        Self::read(images.as_ref(), labels.as_ref(), Self::new)
+    }
+}
+
+/// The QMNIST dataset, a reconstruction of MNIST from NIST with extended labels and a larger
+/// test set, as described in <https://github.com/facebookresearch/qmnist>.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Qmnist {
+    train: QmnistSplit,
+    test: QmnistSplit,
+}
+
+impl Qmnist {
+    /// Loads both splits from `dir`, which holds the four files under their usual names, such as
+    /// `qmnist-train-images-idx3-ubyte` and `qmnist-train-labels-idx2-int`, found as for
+    /// [`Mnist::from_dir`].
+    pub fn from_dir(dir: impl AsRef<Path>) -> Result<Self, DatasetError> {
+        let dir = dir.as_ref();
+        let split = |part: &str| {
+            let name = |file: &str| find_file(dir, &format!("qmnist-{part}-{file}"));
+            QmnistSplit::from_paths(name("images.idx3-ubyte"), name("labels.idx2-int"))
+        };
+        Ok(Qmnist {
+            train: split("train")?,
+            test: split("test")?,
+        })
+    }
+
+    /// Returns the 60,000 training images.
+    pub fn train(&self) -> &QmnistSplit {
+        &self.train
+    }
+
+    /// Returns the 60,000 test images, the first 10,000 of which match the MNIST test set.
+    pub fn test(&self) -> &QmnistSplit {
+        &self.test
+    }
+}
+
+/// A digest of the bytes of a file, against which a copy of the file can be checked.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Digest {
+    Sha256([u8; 32]),
+    Md5([u8; 16]),
+}
+
+impl Digest {
+    /// Reads a SHA-256 digest from its 64 hex digits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hex` is not 64 hex digits, which fails compilation when used in a constant.
+    pub const fn sha256(hex: &str) -> Digest {
+        Digest::Sha256(decode_hex(hex))
+    }
+
+    /// Reads an MD5 digest from its 32 hex digits.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `hex` is not 32 hex digits, which fails compilation when used in a constant.
+    pub const fn md5(hex: &str) -> Digest {
+        Digest::Md5(decode_hex(hex))
+    }
+
+    /// Computes the SHA-256 digest of `bytes`.
+    #[cfg(feature = "checksum")]
+    pub fn sha256_of(bytes: &[u8]) -> Digest {
+        use sha2::Digest as _;
+        Digest::Sha256(sha2::Sha256::digest(bytes).into())
+    }
+
+    /// Computes the MD5 digest of `bytes`.
+    #[cfg(feature = "checksum")]
+    pub fn md5_of(bytes: &[u8]) -> Digest {
+        use md5::Digest as _;
+        Digest::Md5(md5::Md5::digest(bytes).into())
+    }
+
+    /// Checks that `bytes` have this digest, failing with the digest that they have instead.
+    #[cfg(feature = "checksum")]
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), Digest> {
+        let found = match self {
+            Digest::Sha256(_) => Digest::sha256_of(bytes),
+            Digest::Md5(_) => Digest::md5_of(bytes),
+        };
+        if found == *self {
+            Ok(())
+        } else {
+            Err(found)
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            Digest::Sha256(bytes) => bytes,
+            Digest::Md5(bytes) => bytes,
+        }
+    }
+}
+
+/// Formats the digest as its algorithm and hex digits, such as `md5:9fb629c4...`.
+impl fmt::Display for Digest {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Digest::Sha256(_) => "sha256:",
+            Digest::Md5(_) => "md5:",
+        })?;
+        self.bytes()
+            .iter()
+            .try_for_each(|byte| write!(f, "{byte:02x}"))
+    }
+}
+
+const fn decode_hex<const L: usize>(hex: &str) -> [u8; L] {
+    let hex = hex.as_bytes();
+    assert!(hex.len() == 2 * L, "wrong number of hex digits");
+    let mut bytes = [0; L];
+    let mut i = 0;
+    while i < L {
+        bytes[i] = hex_digit(hex[2 * i]) << 4 | hex_digit(hex[2 * i + 1]);
+        i += 1;
+    }
+    bytes
+}
+
+const fn hex_digit(c: u8) -> u8 {
+    match c {
+        b'0'..=b'9' => c - b'0',
+        b'a'..=b'f' => c - b'a' + 10,
+        b'A'..=b'F' => c - b'A' + 10,
+        _ => panic!("invalid hex digit"),
+    }
+}
+
+/// A file of a dataset described by a [`DatasetSpec`], with the digests of known-good copies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileSpec {
+    /// The name of the file, found as for [`Mnist::from_dir`].
+    pub name: &'static str,
+    /// The digest of the uncompressed file, if known.
+    pub digest: Option<Digest>,
+    /// The digest of the gzipped file, as officially distributed, if known.
+    pub gzip_digest: Option<Digest>,
+}
+
+impl FileSpec {
+    /// Describes the file called `name`, without any digests.
+    pub const fn new(name: &'static str) -> Self {
+        FileSpec {
+            name,
+            digest: None,
+            gzip_digest: None,
+        }
+    }
+}
+
+/// Checks the bytes of a file before they are parsed.
+type Verify = fn(&FileSpec, &Path, &[u8]) -> Result<(), DatasetError>;
+
+/// Checks the bytes read from `path` against the digest that `file` gives for them, gzipped or
+/// not. Uncompressed bytes without a digest of their own are checked against a gzipped copy
+/// beside them instead, if there is one. Fails if no digest is known.
+#[cfg(feature = "checksum")]
+fn verify_file(file: &FileSpec, path: &Path, bytes: &[u8]) -> Result<(), DatasetError> {
+    let gzipped = bytes.starts_with(&GZIP_MAGIC);
+    let expected = if gzipped {
+        file.gzip_digest
+    } else {
+        file.digest
+    };
+    let path = path.to_path_buf();
+    match expected {
+        Some(expected) => check_digest(expected, path, bytes),
+        None if !gzipped => verify_against_gzip(file, path, bytes),
+        None => Err(DatasetError::MissingDigest { path }),
+    }
+}
+
+#[cfg(feature = "checksum")]
+fn check_digest(expected: Digest, path: PathBuf, bytes: &[u8]) -> Result<(), DatasetError> {
+    expected
+        .verify(bytes)
+        .map_err(|found| DatasetError::ChecksumMismatch {
+            path,
+            expected,
+            found,
+        })
+}
+
+/// Checks the uncompressed bytes read from `path` against the gzipped copy of `file` beside
+/// them, after checking that copy against the digest that `file` gives for it.
+#[cfg(feature = "checksum")]
+fn verify_against_gzip(file: &FileSpec, path: PathBuf, bytes: &[u8]) -> Result<(), DatasetError> {
+    let (digest, gzip_path) = match (file.gzip_digest, find_gzip(&path, file.name)) {
+        (Some(digest), Some(gzip_path)) => (digest, gzip_path),
+        _ => return Err(DatasetError::MissingDigest { path }),
+    };
+    let read_error = |path: &Path, source: ReadError| DatasetError::Read {
+        path: path.to_path_buf(),
+        source,
+    };
+    let gzipped = fs::read(&gzip_path).map_err(|e| read_error(&gzip_path, e.into()))?;
+    check_digest(digest, gzip_path.clone(), &gzipped)?;
+    let expected = decompress(&gzipped, None).map_err(|e| read_error(&gzip_path, e.into()))?;
+    check_digest(Digest::sha256_of(&expected), path, bytes)
+}
+
+/// Finds a gzipped copy of the file called `name` in the directory of `path`, spelled either
+/// way, if the `gzip` feature is enabled to read it.
+#[cfg(feature = "checksum")]
+fn find_gzip(path: &Path, name: &str) -> Option<PathBuf> {
+    if !cfg!(feature = "gzip") {
+        return None;
+    }
+    let dir = path.parent()?;
+    let official = name.replacen('.', "-", 1);
+    let found = [name, &official]
+        .into_iter()
+        .map(|name| dir.join(format!("{name}.gz")))
+        .find(|path| path.exists());
+    found
+}
+
+/// Reads `file` from `dir`, streaming it with [`IdxArray::from_path`] unless it is to be
+/// checked with `verify`, in which case its bytes are read whole and checked before they are
+/// parsed with [`IdxArray::parse`].
+fn load_file<T: DataFormat, const N: usize>(
+    dir: &Path,
+    file: &FileSpec,
+    verify: Option<Verify>,
+) -> Result<IdxArray<T, N>, DatasetError> {
+    let path = find_file(dir, file.name);
+    let verify = match verify {
+        Some(verify) => verify,
+        None => return read_file(path),
+    };
+    let bytes = match fs::read(&path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return Err(DatasetError::Read {
+                path,
+                source: e.into(),
+            })
+        }
+    };
+    verify(file, &path, &bytes)?;
+    IdxArray::parse(&bytes).map_err(|e| DatasetError::Read {
+        path,
+        source: e.into(),
+    })
+}
+
+/// The files of one split of a dataset described by a [`DatasetSpec`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SplitSpec {
+    /// The file of images.
+    pub images: FileSpec,
+    /// The file of labels.
+    pub labels: FileSpec,
+    /// The number of images in the split.
+    pub len: u32,
+}
+
+/// A description of a dataset laid out like MNIST: a training and a test split, each a file
+/// of `u8` images of the same size and a file of `u8` labels numbering the classes from `0`.
+///
+/// The well-known variants are built in, and others can be described by filling in the fields
+/// and loaded the same way.
+///
+/// So that specs can be constants, the names of the dataset, its files and its classes are
+/// `&'static str`s. A spec built at runtime, such as from a configuration file, has to give
+/// them that lifetime itself, for example with [`Box::leak`] or [`String::leak`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DatasetSpec {
+    /// The name of the dataset, by which it is found in a [`DatasetRegistry`].
+    pub name: &'static str,
+    /// The files of the training split.
+    pub train: SplitSpec,
+    /// The files of the test split.
+    pub test: SplitSpec,
+    /// The height and width of each image.
+    pub image_dims: [u32; 2],
+    /// The name of each class, in the order of their labels.
+    pub classes: &'static [&'static str],
+}
+
+impl DatasetSpec {
+    /// The MNIST handwritten digit dataset, also loaded by [`Mnist`], with the digests of the
+    /// official files both gzipped and not.
+    pub const MNIST: DatasetSpec = DatasetSpec {
+        name: "mnist",
+        train: SplitSpec {
+            images: FileSpec {
+                digest: Some(Digest::sha256(
+                    "ba891046e6505d7aadcbbe25680a0738ad16aec93bde7f9b65e87a2fc25776db",
+                )),
+                gzip_digest: Some(Digest::md5("f68b3c2dcbeaaa9fbdd348bbdeb94873")),
+                ..FileSpec::new(TRAIN_FILES[0])
+            },
+            labels: FileSpec {
+                digest: Some(Digest::sha256(
+                    "65a50cbbf4e906d70832878ad85ccda5333a97f0f4c3dd2ef09a8a9eef7101c5",
+                )),
+                gzip_digest: Some(Digest::md5("d53e105ee54ea40749a09fcbcd1e9432")),
+                ..FileSpec::new(TRAIN_FILES[1])
+            },
+            len: 60_000,
+        },
+        test: SplitSpec {
+            images: FileSpec {
+                digest: Some(Digest::sha256(
+                    "0fa7898d509279e482958e8ce81c8e77db3f2f8254e26661ceb7762c4d494ce7",
+                )),
+                gzip_digest: Some(Digest::md5("9fb629c4189551a2d022fa330f9573f3")),
+                ..FileSpec::new(TEST_FILES[0])
+            },
+            labels: FileSpec {
+                digest: Some(Digest::sha256(
+                    "ff7bcfd416de33731a308c3f266cc351222c34898ecbeaf847f06e48f7ec33f2",
+                )),
+                gzip_digest: Some(Digest::md5("ec29112dd5afa0611ce80d1b7f02629c")),
+                ..FileSpec::new(TEST_FILES[1])
+            },
+            len: 10_000,
+        },
+        image_dims: [28, 28],
+        classes: &["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"],
+    };
+
+    /// The Fashion-MNIST dataset, also loaded by [`FashionMnist`], with the digests of the
+    /// official gzipped files, against which uncompressed files are verified if the gzipped
+    /// files are kept beside them.
+    pub const FASHION_MNIST: DatasetSpec = DatasetSpec {
+        name: "fashion-mnist",
+        train: SplitSpec {
+            images: FileSpec {
+                gzip_digest: Some(Digest::md5("8d4fb7e6c68d591d4c3dfef9ec88bf0d")),
+                ..FileSpec::new(TRAIN_FILES[0])
+            },
+            labels: FileSpec {
+                gzip_digest: Some(Digest::md5("25c81989df183df01b3e8a0aad5dffbe")),
+                ..FileSpec::new(TRAIN_FILES[1])
+            },
+            len: 60_000,
+        },
+        test: SplitSpec {
+            images: FileSpec {
+                gzip_digest: Some(Digest::md5("bef4ecab320f06d8554ea6380940ec79")),
+                ..FileSpec::new(TEST_FILES[0])
+            },
+            labels: FileSpec {
+                gzip_digest: Some(Digest::md5("bb300cfdad3c16e7a12a480ee83cd310")),
+                ..FileSpec::new(TEST_FILES[1])
+            },
+            len: 10_000,
+        },
+        classes: FASHION_CLASSES,
+        ..DatasetSpec::MNIST
+    };
+
+    /// The Kuzushiji-MNIST dataset of cursive Japanese characters, as described in
+    /// <https://github.com/rois-codh/kmnist>, with a hiragana character for each class.
+    pub const KMNIST: DatasetSpec = DatasetSpec {
+        name: "kmnist",
+        train: SplitSpec {
+            images: FileSpec::new(TRAIN_FILES[0]),
+            labels: FileSpec::new(TRAIN_FILES[1]),
+            len: 60_000,
+        },
+        test: SplitSpec {
+            images: FileSpec::new(TEST_FILES[0]),
+            labels: FileSpec::new(TEST_FILES[1]),
+            len: 10_000,
+        },
+        classes: &["お", "き", "す", "つ", "な", "は", "ま", "や", "れ", "を"],
+        ..DatasetSpec::MNIST
+    };
+
+    /// Every built-in dataset.
+    pub const BUILTIN: [DatasetSpec; 3] = [
+        DatasetSpec::MNIST,
+        DatasetSpec::FASHION_MNIST,
+        DatasetSpec::KMNIST,
+    ];
+
+    /// Loads both splits of the dataset from `dir`, checking that the images and labels have
+    /// the dimensions given by the spec and that each label is one of its classes.
+    pub fn load(&self, dir: impl AsRef<Path>) -> Result<Dataset, DatasetError> {
+        self.load_with(dir.as_ref(), None)
+    }
+
+    /// Loads the dataset as [`DatasetSpec::load`] does, first checking the bytes of each file
+    /// against the digest that the spec gives for it, gzipped or not.
+    ///
+    /// An uncompressed file for which the spec only gives the digest of the gzipped file, such
+    /// as those of [`DatasetSpec::FASHION_MNIST`], is checked against the gzipped file if it is
+    /// found beside it and the `gzip` feature is enabled.
+    ///
+    /// Fails with [`DatasetError::MissingDigest`] on a file that cannot be checked this way,
+    /// such as any file of [`DatasetSpec::KMNIST`], rather than loading it unchecked.
+    #[cfg(feature = "checksum")]
+    pub fn load_verified(&self, dir: impl AsRef<Path>) -> Result<Dataset, DatasetError> {
+        self.load_with(dir.as_ref(), Some(verify_file))
+    }
+
+    fn load_with(&self, dir: &Path, verify: Option<Verify>) -> Result<Dataset, DatasetError> {
+        Ok(Dataset {
+            spec: *self,
+            train: self.load_split(dir, &self.train, verify)?,
+            test: self.load_split(dir, &self.test, verify)?,
+        })
+    }
+
+    fn load_split(
+        &self,
+        dir: &Path,
+        split: &SplitSpec,
+        verify: Option<Verify>,
+    ) -> Result<LabeledIdx<u8, u8, 3>, DatasetError> {
+        let [height, width] = self.image_dims;
+        let images = load_file(dir, &split.images, verify)?;
+        check_dims(&images, [split.len, height, width])?;
+        let labels: IdxArray<u8, 1> = load_file(dir, &split.labels, verify)?;
+        check_dims(&labels, [split.len])?;
+        let labeled = LabeledIdx::new(images, labels)?;
+        let mut labels = labeled.labels().iter().enumerate();
+        let num_classes = self.classes.len();
+        if let Some((index, &label)) = labels.find(|(_, &l)| usize::from(l) >= num_classes) {
+            let label = label.into();
+            return Err(DatasetError::InvalidLabel { index, label });
+        }
+        Ok(labeled)
+    }
+}
+
+fn check_dims<T, const N: usize>(
+    array: &IdxArray<T, N>,
+    expected: [u32; N],
+) -> Result<(), DatasetError> {
+    if array.dims() == expected {
+        Ok(())
+    } else {
+        Err(DatasetError::DimsMismatch {
+            expected: expected.to_vec(),
+            found: array.dims().to_vec(),
+        })
+    }
+}
+
+/// A dataset loaded as described by a [`DatasetSpec`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Dataset {
+    spec: DatasetSpec,
+    train: LabeledIdx<u8, u8, 3>,
+    test: LabeledIdx<u8, u8, 3>,
+}
+
+impl Dataset {
+    /// Returns the spec that the dataset was loaded from.
+    pub fn spec(&self) -> &DatasetSpec {
+        &self.spec
+    }
+
+    /// Returns the training images with their labels.
+    pub fn train(&self) -> &LabeledIdx<u8, u8, 3> {
+        &self.train
+    }
+
+    /// Returns the test images with their labels.
+    pub fn test(&self) -> &LabeledIdx<u8, u8, 3> {
+        &self.test
+    }
+
+    /// Returns the name of the class numbered `label`, or `None` if there is no such class.
+    pub fn class_name(&self, label: u8) -> Option<&'static str> {
+        self.spec.classes.get(usize::from(label)).copied()
+    }
+}
+
+/// A collection of [`DatasetSpec`]s, looked up by name, starting with the built-in ones.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DatasetRegistry {
+    specs: Vec<DatasetSpec>,
+}
+
+impl DatasetRegistry {
+    /// Creates a registry holding [`DatasetSpec::BUILTIN`].
+    pub fn new() -> Self {
+        DatasetRegistry {
+            specs: DatasetSpec::BUILTIN.to_vec(),
+        }
+    }
+
+    /// Adds `spec` to the registry, returning the spec of the same name that it replaces, if
+    /// any.
+    pub fn register(&mut self, spec: DatasetSpec) -> Option<DatasetSpec> {
+        match self.specs.iter_mut().find(|s| s.name == spec.name) {
+            Some(existing) => Some(mem::replace(existing, spec)),
+            None => {
+                self.specs.push(spec);
+                None
+            }
+        }
+    }
+
+    /// Returns the spec called `name`, if there is one.
+    pub fn get(&self, name: &str) -> Option<&DatasetSpec> {
+        self.specs.iter().find(|spec| spec.name == name)
+    }
+
+    /// Loads the dataset called `name` from `dir`, as [`DatasetSpec::load`] does.
+    pub fn load(&self, name: &str, dir: impl AsRef<Path>) -> Result<Dataset, DatasetError> {
+        match self.get(name) {
+            Some(spec) => spec.load(dir),
+            None => Err(DatasetError::UnknownDataset {
+                name: name.to_owned(),
+            }),
+        }
+    }
+
+    /// Returns every spec, in the order they were added.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &DatasetSpec> {
+        self.specs.iter()
+    }
+}
+
+impl Default for DatasetRegistry {
+    fn default() -> Self {
+        DatasetRegistry::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DatasetError;
+    use super::DatasetRegistry;
+    use super::DatasetSpec;
+    use super::Digest;
+    use super::Emnist;
+    use super::EmnistSplit;
+    use super::FashionLabel;
+    use super::FashionMnist;
+    use super::FashionMnistSplit;
+    use super::FileSpec;
+    use super::LabeledIdx;
+    use super::Mnist;
+    use super::MnistSplit;
+    use super::Qmnist;
+    use super::QmnistLabel;
+    use super::QmnistSplit;
+    use super::SplitSpec;
+    use crate::IdxArray;
+    use std::env;
+    use std::fs;
+    use std::path::Path;
+    use std::path::PathBuf;
+    use std::process;
+
+    /// A scratch directory for a test, removed when dropped even if the test fails.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("read-idx-array-{name}-{}", process::id()));
+            fs::create_dir_all(&dir).expect("create dir");
+            TempDir(dir)
+        }
+
+        fn join(&self, file: impl AsRef<Path>) -> PathBuf {
+            self.0.join(file)
+        }
+    }
+
+    impl AsRef<Path> for TempDir {
+        fn as_ref(&self) -> &Path {
+            &self.0
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    #[test]
+    fn test_mnist() {
+        let mnist = Mnist::from_dir("data").expect("load dataset");
+        assert_eq!(mnist.train().len(), 60_000);
+        assert_eq!(mnist.test().len(), 10_000);
+        assert_eq!(mnist.test().images().dims(), [10_000, 28, 28]);
+
+        match Mnist::from_dir("src") {
+            Err(DatasetError::Read { path, .. }) => {
+                assert!(path.ends_with("train-images.idx3-ubyte"))
+            }
+            other => panic!("expected read error, got {other:?}"),
+        }
+
+        // Checked against the spec, as for `DatasetSpec::MNIST.load`.
+        let dir = TempDir::new("mnist");
+        let images = IdxArray::from_dims_data([2, 28, 28], vec![0u8; 2 * 28 * 28]).expect("images");
+        fs::write(dir.join("train-images.idx3-ubyte"), images.to_bytes()).expect("write images");
+        match Mnist::from_dir(&dir) {
+            Err(DatasetError::DimsMismatch { expected, found }) => {
+                assert_eq!((expected, found), (vec![60_000, 28, 28], vec![2, 28, 28]))
+            }
+            other => panic!("expected dims mismatch, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_mnist_split_validation() {
+        let images = IdxArray::from_dims_data([2, 28, 28], vec![0; 2 * 28 * 28]).expect("images");
+        let labels = IdxArray::from_dims_data([3], vec![0, 1, 2]).expect("labels");
+        match MnistSplit::new(images.clone(), labels) {
+            Err(DatasetError::LengthMismatch {
+                samples: 2,
+                labels: 3,
+            }) => {}
+            other => panic!("expected length mismatch, got {other:?}"),
+        }
+        let labels = IdxArray::from_dims_data([2], vec![9, 10]).expect("labels");
+        match MnistSplit::new(images, labels) {
+            Err(DatasetError::InvalidLabel {
+                index: 1,
+                label: 10,
+            }) => {}
+            other => panic!("expected invalid label, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_labeled_idx() {
+        let samples = IdxArray::from_dims_data([3, 2], vec![1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0]);
+        let labels = IdxArray::from_dims_data([3], vec![-1i32, 0, 1]).expect("labels");
+        let labeled = LabeledIdx::new(samples.expect("samples"), labels).expect("pair up");
+        assert_eq!(labeled.sample_dims(), [2]);
+        assert_eq!(labeled.get(1), Some((&[3.0, 4.0][..], &0)));
+        assert_eq!(labeled.get(3), None);
+        let pairs: Vec<_> = labeled.iter().collect();
+        assert_eq!(pairs.len(), 3);
+        assert_eq!(pairs[2], (&[5.0, 6.0][..], &1));
+
+        let mnist = super::MnistSplit::from_paths(
+            "data/t10k-images.idx3-ubyte",
+            "data/t10k-labels.idx1-ubyte",
+        )
+        .expect("load split");
+        let (image, &label) = mnist.as_labeled().get(0).expect("first sample");
+        assert_eq!((image.len(), label), (28 * 28, mnist.labels()[0]));
+
+        let empty = IdxArray::<u8, 1>::from_dims_data([0], vec![]).expect("labels");
+        let dims = [0, u32::MAX, u32::MAX, u32::MAX];
+        let samples = IdxArray::<u8, 4>::from_dims_data(dims, vec![]).expect("samples");
+        match LabeledIdx::new(samples, empty.clone()) {
+            Err(DatasetError::SampleDims { dims: found }) => assert_eq!(found, dims),
+            other => panic!("expected sample dims error, got {other:?}"),
+        }
+        let samples = IdxArray::<u8, 0>::from_dims_data([], vec![7]).expect("samples");
+        match LabeledIdx::new(samples, empty) {
+            Err(DatasetError::SampleDims { dims }) => assert!(dims.is_empty()),
+            other => panic!("expected sample dims error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_fashion_mnist() {
+        assert_eq!(FashionLabel::try_from(0), Ok(FashionLabel::TShirt));
+        assert_eq!(FashionLabel::try_from(9), Ok(FashionLabel::AnkleBoot));
+        assert_eq!(FashionLabel::try_from(10), Err(10));
+        assert_eq!(FashionLabel::AnkleBoot.to_string(), "Ankle boot");
+        assert!(FashionLabel::ALL
+            .iter()
+            .all(|&l| FashionLabel::try_from(u8::from(l)) == Ok(l)));
+
+        // Fashion-MNIST has the same layout as the bundled MNIST files.
+        let split = FashionMnistSplit::from_paths(
+            "data/t10k-images.idx3-ubyte",
+            "data/t10k-labels.idx1-ubyte",
+        )
+        .expect("load split");
+        assert_eq!(split.len(), 10_000);
+        let raw = IdxArray::<u8, 1>::from_path("data/t10k-labels.idx1-ubyte").expect("labels");
+        assert!(split
+            .labels()
+            .iter()
+            .map(|&l| u8::from(l))
+            .eq(raw.into_sequence()));
+        let fashion = FashionMnist::from_dir("data").expect("load dataset");
+        assert_eq!(fashion.test(), &split);
+    }
+
+    #[test]
+    fn test_emnist_labels() {
+        for split in EmnistSplit::ALL {
+            let chars: Vec<_> = (0..=u8::MAX).filter_map(|l| split.label_char(l)).collect();
+            assert_eq!(chars.len(), split.num_classes(), "{split}");
+        }
+        assert_eq!(EmnistSplit::ByClass.label_char(61), Some('z'));
+        assert_eq!(EmnistSplit::Balanced.label_char(36), Some('a'));
+        assert_eq!(EmnistSplit::Balanced.label_char(46), Some('t'));
+        assert_eq!(EmnistSplit::Letters.label_char(0), None);
+        assert_eq!(EmnistSplit::Letters.label_char(26), Some('Z'));
+        assert_eq!(EmnistSplit::Digits.label_char(10), None);
+    }
+
+    #[test]
+    fn test_emnist() {
+        let dir = TempDir::new("emnist");
+        let images = IdxArray::from_dims_data([1, 2, 3], vec![1u8, 2, 3, 4, 5, 6]).expect("images");
+        let labels = IdxArray::from_dims_data([1], vec![26u8]).expect("labels");
+        for part in ["train", "test"] {
+            let name = |file| dir.join(format!("emnist-letters-{part}-{file}"));
+            fs::write(name("images-idx3-ubyte"), images.to_bytes()).expect("write images");
+            fs::write(name("labels-idx1-ubyte"), labels.to_bytes()).expect("write labels");
+        }
+        let emnist = Emnist::from_dir(&dir, EmnistSplit::Letters).expect("load dataset");
+        let (image, &label) = emnist.train().as_labeled().get(0).expect("first sample");
+        assert_eq!(emnist.train().images().dims(), [1, 3, 2]);
+        assert_eq!(image, [1, 4, 2, 5, 3, 6]);
+        assert_eq!(emnist.label_char(label), Some('Z'));
+        let digits = Emnist::from_dir(&dir, EmnistSplit::Digits);
+        assert!(matches!(digits, Err(DatasetError::Read { .. })));
+
+        // Images with no pixels are well-formed, if useless.
+        for (dims, transposed) in [([0, 0, 0], [0, 0, 0]), ([5, 0, 28], [5, 28, 0])] {
+            let images = IdxArray::<u8, 3>::from_dims_data(dims, vec![]).expect("images");
+            let len = dims[0] as usize;
+            let labels = IdxArray::from_dims_data([dims[0]], vec![26u8; len]).expect("labels");
+            for part in ["train", "test"] {
+                let name = |file| dir.join(format!("emnist-letters-{part}-{file}"));
+                fs::write(name("images-idx3-ubyte"), images.to_bytes()).expect("write images");
+                fs::write(name("labels-idx1-ubyte"), labels.to_bytes()).expect("write labels");
+            }
+            let emnist = Emnist::from_dir(&dir, EmnistSplit::Letters).expect("load dataset");
+            assert_eq!(emnist.test().images().dims(), transposed);
+            assert_eq!(emnist.test().len(), len);
+        }
+    }
+
+    #[test]
+    fn test_qmnist() {
+        let dir = TempDir::new("qmnist");
+        let images = IdxArray::from_dims_data([2, 1, 1], vec![7u8, 3]).expect("images");
+        let rows = vec![
+            3, 4, 2100, 5, 51, 280_000, 0, 0, 7, 0, 12, 149, 55, 17, 0, 0,
+        ];
+        let labels = IdxArray::from_dims_data([2, 8], rows).expect("labels");
+        for part in ["train", "test"] {
+            let name = |file| dir.join(format!("qmnist-{part}-{file}"));
+            fs::write(name("images-idx3-ubyte"), images.to_bytes()).expect("write images");
+            fs::write(name("labels-idx2-int"), labels.to_bytes()).expect("write labels");
+        }
+        let qmnist = Qmnist::from_dir(&dir).expect("load dataset");
+        let label = qmnist.test().labels()[0];
+        assert_eq!(label.digit, 3);
+        assert_eq!(label.hsf_series, 4);
+        assert_eq!(label.writer_id, 2100);
+        assert_eq!(label.nist_index, 280_000);
+        assert_eq!(label.to_row(), labels.data()[..8]);
+        assert_eq!(qmnist.train().labels()[1].writer_digit_index, 149);
+
+        let wide = IdxArray::from_dims_data([1, 9], vec![0i32; 9]).expect("labels");
+        match QmnistSplit::new(images.clone(), wide) {
+            Err(DatasetError::DimsMismatch { expected, found }) => {
+                assert_eq!((expected, found), (vec![1, 8], vec![1, 9]))
+            }
+            other => panic!("expected dims mismatch, got {other:?}"),
+        }
+        let bad = IdxArray::from_dims_data(
+            [2, 8],
+            vec![0, 0, 0, 0, 0, 0, 0, 0, -1, 0, 0, 0, 0, 0, 0, 0],
+        );
+        match QmnistSplit::new(images, bad.expect("labels")) {
+            Err(DatasetError::InvalidLabel {
+                index: 1,
+                label: -1,
+            }) => {}
+            other => panic!("expected invalid label, got {other:?}"),
+        }
+        assert_eq!(QmnistLabel::from_row([10, 0, 0, 0, 0, 0, 0, 0]), Err(10));
+    }
+
+    #[test]
+    fn test_dataset_spec() {
+        let mnist = DatasetSpec::MNIST.load("data").expect("load dataset");
+        assert_eq!(mnist.train().len(), 60_000);
+        assert_eq!(mnist.test().sample_dims(), [28, 28]);
+        assert_eq!(mnist.class_name(7), Some("7"));
+        assert_eq!(mnist.class_name(10), None);
+        assert_eq!(DatasetSpec::FASHION_MNIST.classes[9], "Ankle boot");
+
+        let dir = TempDir::new("spec");
+        let images = IdxArray::from_dims_data([2, 1, 2], vec![1u8, 2, 3, 4]).expect("images");
+        let labels = IdxArray::from_dims_data([2], vec![1u8, 0]).expect("labels");
+        fs::write(dir.join("images"), images.to_bytes()).expect("write images");
+        fs::write(dir.join("labels"), labels.to_bytes()).expect("write labels");
+        let split = SplitSpec {
+            images: FileSpec::new("images"),
+            labels: FileSpec::new("labels"),
+            len: 2,
+        };
+        let spec = DatasetSpec {
+            name: "tiny",
+            train: split,
+            test: split,
+            image_dims: [1, 2],
+            classes: &["zero", "one"],
+        };
+        let mut registry = DatasetRegistry::new();
+        assert_eq!(registry.register(spec), None);
+        assert_eq!(registry.iter().len(), DatasetSpec::BUILTIN.len() + 1);
+        let spec = *registry.get("tiny").expect("registered spec");
+        let tiny = spec.load(&dir).expect("load dataset");
+        assert_eq!(registry.load("tiny", &dir).expect("load dataset"), tiny);
+        match registry.load("huge", &dir) {
+            Err(DatasetError::UnknownDataset { name }) => assert_eq!(name, "huge"),
+            other => panic!("expected unknown dataset, got {other:?}"),
+        }
+        assert_eq!(tiny.test().get(0), Some((&[1, 2][..], &1)));
+        assert_eq!(tiny.class_name(1), Some("one"));
+        let wrong_dims = DatasetSpec {
+            image_dims: [2, 1],
+            ..spec
+        }
+        .load(&dir);
+        let one_class = DatasetSpec {
+            classes: &["zero"],
+            ..spec
+        }
+        .load(&dir);
+        match wrong_dims {
+            Err(DatasetError::DimsMismatch { expected, found }) => {
+                assert_eq!((expected, found), (vec![2, 2, 1], vec![2, 1, 2]))
+            }
+            other => panic!("expected dims mismatch, got {other:?}"),
+        }
+        match one_class {
+            Err(DatasetError::InvalidLabel { index: 0, label: 1 }) => {}
+            other => panic!("expected invalid label, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_digest() {
+        let digest = Digest::md5("9FB629C4189551A2D022FA330F9573F3");
+        assert_eq!(digest.to_string(), "md5:9fb629c4189551a2d022fa330f9573f3");
+        assert_eq!(DatasetSpec::MNIST.test.images.gzip_digest, Some(digest));
+        match Digest::sha256(&"00".repeat(32)) {
+            Digest::Sha256(bytes) => assert_eq!(bytes, [0; 32]),
+            other => panic!("expected SHA-256 digest, got {other:?}"),
+        }
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn test_checksum() {
+        let labels = fs::read("data/t10k-labels.idx1-ubyte").expect("labels");
+        let expected = DatasetSpec::MNIST.test.labels.digest.expect("digest");
+        assert_eq!(expected.verify(&labels), Ok(()));
+        assert_eq!(
+            Digest::md5_of(&labels),
+            Digest::md5("27ae3e4e09519cfbb04c329615203637")
+        );
+        DatasetSpec::MNIST
+            .load_verified("data")
+            .expect("load dataset");
+
+        let mut spec = DatasetSpec::MNIST;
+        spec.test.labels.digest = Some(Digest::sha256_of(b"not the labels"));
+        match spec.load_verified("data") {
+            Err(DatasetError::ChecksumMismatch {
+                path,
+                expected: Digest::Sha256(_),
+                found,
+            }) => {
+                assert!(path.ends_with("t10k-labels.idx1-ubyte"));
+                assert_eq!(found, Digest::sha256_of(&labels));
+            }
+            other => panic!("expected checksum mismatch, got {other:?}"),
+        }
+
+        // The bundled files stand in for datasets that only have digests of other files.
+        for spec in [DatasetSpec::FASHION_MNIST, DatasetSpec::KMNIST] {
+            match spec.load_verified("data") {
+                Err(DatasetError::MissingDigest { path }) => {
+                    assert!(path.ends_with("train-images.idx3-ubyte"))
+                }
+                other => panic!("expected missing digest, got {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "checksum", feature = "gzip"))]
+    #[test]
+    fn test_checksum_beside_gzip() {
+        let dir = TempDir::new("checksum-gzip");
+        let mut spec = DatasetSpec::FASHION_MNIST;
+        spec.train.len = 2;
+        spec.test.len = 1;
+        let images = |len| {
+            let images = IdxArray::from_dims_data([len, 28, 28], vec![0u8; len as usize * 784]);
+            let images = images.expect("images");
+            (
+                images.to_bytes(),
+                images.write_gzip_to(vec![]).expect("compress"),
+            )
+        };
+        let labels = |len| {
+            let labels = IdxArray::from_dims_data([len], vec![9u8; len as usize]).expect("labels");
+            (
+                labels.to_bytes(),
+                labels.write_gzip_to(vec![]).expect("compress"),
+            )
+        };
+        let files = [
+            (spec.train.images.name, images(2)),
+            (spec.train.labels.name, labels(2)),
+            (spec.test.images.name, images(1)),
+            (spec.test.labels.name, labels(1)),
+        ];
+        let mut gzip_digests = vec![];
+        for (name, (bytes, gzipped)) in files {
+            // Laid out as the official files are after `gunzip --keep`.
+            let path = dir.join(name.replacen('.', "-", 1));
+            fs::write(&path, bytes).expect("write file");
+            fs::write(path.with_extension("gz"), &gzipped).expect("write gzipped file");
+            gzip_digests.push(Digest::md5_of(&gzipped));
+        }
+
+        // The built-in digests are those of the official files, which these are not.
+        match spec.load_verified(&dir) {
+            Err(DatasetError::ChecksumMismatch { path, .. }) => {
+                assert!(path.ends_with("train-images-idx3-ubyte.gz"))
+            }
+            other => panic!("expected checksum mismatch, got {other:?}"),
+        }
+
+        let files = [
+            &mut spec.train.images,
+            &mut spec.train.labels,
+            &mut spec.test.images,
+            &mut spec.test.labels,
+        ];
+        for (file, digest) in files.into_iter().zip(gzip_digests) {
+            file.gzip_digest = Some(digest);
+        }
+        let dataset = spec.load_verified(&dir).expect("load dataset");
+        assert_eq!(dataset, spec.load(&dir).expect("load dataset"));
+
+        let labels_path = dir.join("t10k-labels-idx1-ubyte");
+        let original = fs::read(&labels_path).expect("labels");
+        let mut changed = original.clone();
+        changed[8] = 8;
+        fs::write(&labels_path, &changed).expect("write labels");
+        match spec.load_verified(&dir) {
+            Err(DatasetError::ChecksumMismatch {
+                path,
+                expected,
+                found,
+            }) => {
+                assert_eq!(path, labels_path);
+                assert_eq!(expected, Digest::sha256_of(&original));
+                assert_eq!(found, Digest::sha256_of(&changed));
+            }
+            other => panic!("expected checksum mismatch, got {other:?}"),
+        }
+
+        fs::remove_file(labels_path.with_extension("gz")).expect("remove gzipped labels");
+        match spec.load_verified(&dir) {
+            Err(DatasetError::MissingDigest { path }) => assert_eq!(path, labels_path),
+            other => panic!("expected missing digest, got {other:?}"),
+        }
+    }
+}
@@ -1,112 +1,840 @@
 //! Reads `IDX` files as described in <http://yann.lecun.com/exdb/mnist/>
+//!
+//! Without the default `std` feature the crate is `no_std`, needing only `alloc`.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+
+extern crate alloc;
+
+use crate::compress::decompress;
+use crate::options::recover_records;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+#[cfg(feature = "image")]
 use image::GrayImage;
-use nom::bytes::complete::tag;
-use nom::combinator::eof;
+use nom::bytes::complete::take;
+use nom::combinator::map;
 use nom::combinator::map_res;
+use nom::combinator::rest_len;
 use nom::multi::count;
 use nom::number::complete::be_f32;
 use nom::number::complete::be_f64;
 use nom::number::complete::be_i16;
 use nom::number::complete::be_i32;
 use nom::number::complete::be_i8;
+use nom::number::complete::be_u16;
 use nom::number::complete::be_u32;
 use nom::number::complete::be_u8;
-use std::fmt;
+#[cfg(feature = "rayon")]
+use rayon::prelude::*;
+#[cfg(feature = "std")]
+use std::error;
+
+mod compress;
+#[cfg(feature = "std")]
+mod dataset;
+mod options;
+#[cfg(feature = "std")]
+mod read;
+#[cfg(feature = "std")]
+mod record;
+mod view;
+mod write;
+
+#[cfg(feature = "std")]
+pub use dataset::Dataset;
+#[cfg(feature = "std")]
+pub use dataset::DatasetError;
+#[cfg(feature = "std")]
+pub use dataset::DatasetRegistry;
+#[cfg(feature = "std")]
+pub use dataset::DatasetSpec;
+#[cfg(feature = "std")]
+pub use dataset::Digest;
+#[cfg(feature = "std")]
+pub use dataset::Emnist;
+#[cfg(feature = "std")]
+pub use dataset::EmnistSplit;
+#[cfg(feature = "std")]
+pub use dataset::FashionLabel;
+#[cfg(feature = "std")]
+pub use dataset::FashionMnist;
+#[cfg(feature = "std")]
+pub use dataset::FashionMnistSplit;
+#[cfg(feature = "std")]
+pub use dataset::FileSpec;
+#[cfg(feature = "std")]
+pub use dataset::ImageSplit;
+#[cfg(feature = "std")]
+pub use dataset::LabeledIdx;
+#[cfg(feature = "std")]
+pub use dataset::Mnist;
+#[cfg(feature = "std")]
+pub use dataset::MnistSplit;
+#[cfg(feature = "std")]
+pub use dataset::Qmnist;
+#[cfg(feature = "std")]
+pub use dataset::QmnistLabel;
+#[cfg(feature = "std")]
+pub use dataset::QmnistSplit;
+#[cfg(feature = "std")]
+pub use dataset::SplitSpec;
+pub use options::DimConstraint;
+pub use options::ParseOptions;
+pub use options::ParseWarning;
+#[cfg(feature = "std")]
+pub use read::ReadError;
+#[cfg(feature = "std")]
+pub use record::IdxRandomAccessReader;
+#[cfg(feature = "std")]
+pub use record::IdxRecordReader;
+#[cfg(feature = "mmap")]
+pub use view::IdxMmap;
+pub use view::IdxView;
+#[cfg(feature = "std")]
+pub use write::IdxWriter;
 
 /// Error from parsing the `IDX` file.
-#[derive(Debug, Clone)]
-pub struct Error;
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Error {
+    offset: usize,
+    kind: ErrorKind,
+}
+
+impl Error {
+    /// Returns the byte offset into the input of the field that failed to parse.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns which check failed.
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+}
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "IDX parse error")
+        write!(f, "IDX parse error at byte {}: {}", self.offset, self.kind)
+    }
+}
+
+#[cfg(feature = "std")]
+impl error::Error for Error {}
+
+/// The check that failed while parsing an `IDX` file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    /// The file does not start with two zero bytes.
+    ZeroPrefix { found: u16 },
+    /// The type byte does not match the requested element type.
+    MagicByte { expected: u8, found: u8 },
+    /// The type byte is not one of the element types known to `IDX` files.
+    UnknownDataType { found: u8 },
+    /// The number of dimensions does not match the requested rank.
+    NumDims { expected: usize, found: usize },
+    /// The total size of the array does not fit in a `usize`.
+    DimsOverflow { dims: Vec<u32> },
+    /// The length of an axis does not meet the constraint given in the [`ParseOptions`].
+    DimMismatch {
+        axis: usize,
+        expected: DimConstraint,
+        found: u32,
+    },
+    /// The input ends early: at least `expected` bytes were needed, but only `found` remain.
+    Truncated { expected: usize, found: usize },
+    /// The input continues for `found` bytes after the end of the payload.
+    TrailingBytes { found: usize },
+    /// The array has more elements than the limit given in the [`ParseOptions`].
+    TooManyElements { limit: usize, found: usize },
+    /// The payload is longer than the limit given in the [`ParseOptions`].
+    TooManyBytes { limit: usize, found: usize },
+    /// The input looks compressed, but could not be decompressed.
+    Decompression { reason: String },
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::ZeroPrefix { found } => {
+                write!(f, "expected zero prefix 0x0000, found {found:#06X}")
+            }
+            ErrorKind::MagicByte { expected, found } => write!(
+                f,
+                "expected magic {expected:#04X} ({}), found {found:#04X} ({})",
+                format_name(*expected),
+                format_name(*found),
+            ),
+            ErrorKind::UnknownDataType { found } => {
+                write!(f, "expected a known magic byte, found {found:#04X}")
+            }
+            ErrorKind::NumDims { expected, found } => {
+                write!(f, "expected {expected} dimensions, found {found}")
+            }
+            ErrorKind::DimsOverflow { dims } => {
+                write!(f, "dimensions {dims:?} are too large to address")
+            }
+            ErrorKind::DimMismatch {
+                axis,
+                expected,
+                found,
+            } => write!(f, "expected axis {axis} to be {expected}, found {found}"),
+            ErrorKind::Truncated { expected, found } => {
+                write!(f, "expected {expected} more bytes, found {found}")
+            }
+            ErrorKind::TrailingBytes { found } => {
+                write!(f, "expected end of input, found {found} trailing bytes")
+            }
+            ErrorKind::TooManyElements { limit, found } => {
+                write!(f, "expected at most {limit} elements, found {found}")
+            }
+            ErrorKind::TooManyBytes { limit, found } => {
+                write!(
+                    f,
+                    "expected at most {limit} bytes of payload, found {found}"
+                )
+            }
+            ErrorKind::Decompression { reason } => write!(f, "failed to decompress: {reason}"),
+        }
+    }
+}
+
+/// Names the element type for a magic byte, for use in error messages.
+fn format_name(magic: u8) -> &'static str {
+    DataType::from_magic_byte(magic).map_or("unknown", DataType::name)
+}
+
+/// Element types known to `IDX` files, as named by the magic byte in the header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DataType {
+    U8,
+    I8,
+    I16,
+    I32,
+    F32,
+    F64,
+}
+
+impl DataType {
+    /// Returns the element type denoted by `magic`, if there is one.
+    pub fn from_magic_byte(magic: u8) -> Option<Self> {
+        match magic {
+            0x08 => Some(DataType::U8),
+            0x09 => Some(DataType::I8),
+            0x0B => Some(DataType::I16),
+            0x0C => Some(DataType::I32),
+            0x0D => Some(DataType::F32),
+            0x0E => Some(DataType::F64),
+            _ => None,
+        }
+    }
+
+    /// Returns the magic byte denoting this element type.
+    pub fn magic_byte(self) -> u8 {
+        match self {
+            DataType::U8 => u8::MAGIC_BYTE,
+            DataType::I8 => i8::MAGIC_BYTE,
+            DataType::I16 => i16::MAGIC_BYTE,
+            DataType::I32 => i32::MAGIC_BYTE,
+            DataType::F32 => f32::MAGIC_BYTE,
+            DataType::F64 => f64::MAGIC_BYTE,
+        }
+    }
+
+    /// Returns the number of bytes each element takes up in the file.
+    pub fn size(self) -> usize {
+        match self {
+            DataType::U8 | DataType::I8 => 1,
+            DataType::I16 => 2,
+            DataType::I32 | DataType::F32 => 4,
+            DataType::F64 => 8,
+        }
+    }
+
+    /// Returns the name of the corresponding Rust type.
+    pub fn name(self) -> &'static str {
+        match self {
+            DataType::U8 => "u8",
+            DataType::I8 => "i8",
+            DataType::I16 => "i16",
+            DataType::I32 => "i32",
+            DataType::F32 => "f32",
+            DataType::F64 => "f64",
+        }
+    }
+}
+
+impl fmt::Display for DataType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The number of bytes of payload that each thread decodes at a time.
+#[cfg(feature = "rayon")]
+const PAR_CHUNK_LEN: usize = 1 << 16;
+
+/// Error threaded through the `nom` parsers, keeping the input that remained where parsing
+/// stopped so that it can be turned into a byte offset.
+#[derive(Debug)]
+struct ParseError<'a> {
+    input: &'a [u8],
+    kind: ErrorKind,
+}
+
+impl<'a> nom::error::ParseError<&'a [u8]> for ParseError<'a> {
+    fn from_error_kind(input: &'a [u8], _: nom::error::ErrorKind) -> Self {
+        // The `nom` primitives used here only fail by running out of input.
+        let kind = ErrorKind::Truncated {
+            expected: input.len() + 1,
+            found: input.len(),
+        };
+        ParseError { input, kind }
+    }
+
+    fn append(_: &'a [u8], _: nom::error::ErrorKind, other: Self) -> Self {
+        other
+    }
+}
+
+impl<'a> nom::error::FromExternalError<&'a [u8], ErrorKind> for ParseError<'a> {
+    fn from_external_error(input: &'a [u8], _: nom::error::ErrorKind, kind: ErrorKind) -> Self {
+        ParseError { input, kind }
     }
 }
 
 type IResult<'a, T> = Result<(&'a [u8], T), nom::Err<nom::error::Error<&'a [u8]>>>;
+type HResult<'a, T> = nom::IResult<&'a [u8], T, ParseError<'a>>;
 
 mod private {
-    pub trait Sealed {}
+    use super::AnyIdxArray;
+    use alloc::vec::Vec;
+
+    pub trait Sealed: Sized + Copy + Default + Send + Sync {
+        fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray;
+        fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)>;
+
+        /// Decodes `bytes`, which holds as many big-endian elements as `data` has room for, into
+        /// `data` in one pass that the compiler can vectorize.
+        fn decode_into(bytes: &[u8], data: &mut [Self]);
+    }
 }
 
 /// Data types that are known to `IDX` files. Cannot extend without extending the file format
 /// itself.
 pub trait DataFormat: private::Sealed {
     const MAGIC_BYTE: u8;
+    const DATA_TYPE: DataType;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self>;
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>);
 }
 
-impl private::Sealed for u8 {}
+impl private::Sealed for u8 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::U8(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::U8(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        data.copy_from_slice(bytes);
+    }
+}
 impl DataFormat for u8 {
     const MAGIC_BYTE: u8 = 0x08;
+    const DATA_TYPE: DataType = DataType::U8;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_u8(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
 }
 
-impl private::Sealed for i8 {}
+impl private::Sealed for i8 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::I8(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::I8(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        for (x, out) in bytes.iter().zip(data) {
+            *out = *x as i8;
+        }
+    }
+}
 impl DataFormat for i8 {
     const MAGIC_BYTE: u8 = 0x09;
+    const DATA_TYPE: DataType = DataType::I8;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_i8(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
 }
 
-impl private::Sealed for i16 {}
+impl private::Sealed for i16 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::I16(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::I16(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        for (x, out) in bytes.chunks_exact(mem::size_of::<Self>()).zip(data) {
+            *out = i16::from_be_bytes(x.try_into().expect("element size"));
+        }
+    }
+}
 impl DataFormat for i16 {
     const MAGIC_BYTE: u8 = 0x0B;
+    const DATA_TYPE: DataType = DataType::I16;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_i16(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
 }
 
-impl private::Sealed for i32 {}
+impl private::Sealed for i32 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::I32(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::I32(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        for (x, out) in bytes.chunks_exact(mem::size_of::<Self>()).zip(data) {
+            *out = i32::from_be_bytes(x.try_into().expect("element size"));
+        }
+    }
+}
 impl DataFormat for i32 {
     const MAGIC_BYTE: u8 = 0x0C;
+    const DATA_TYPE: DataType = DataType::I32;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_i32(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
 }
 
-impl private::Sealed for f32 {}
+impl private::Sealed for f32 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::F32(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::F32(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        for (x, out) in bytes.chunks_exact(mem::size_of::<Self>()).zip(data) {
+            *out = f32::from_be_bytes(x.try_into().expect("element size"));
+        }
+    }
+}
 impl DataFormat for f32 {
     const MAGIC_BYTE: u8 = 0x0D;
+    const DATA_TYPE: DataType = DataType::F32;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_f32(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
 }
 
-impl private::Sealed for f64 {}
+impl private::Sealed for f64 {
+    fn into_any(dims: Vec<u32>, data: Vec<Self>) -> AnyIdxArray {
+        AnyIdxArray::F64(dims, data)
+    }
+
+    fn from_any(array: AnyIdxArray) -> Option<(Vec<u32>, Vec<Self>)> {
+        match array {
+            AnyIdxArray::F64(dims, data) => Some((dims, data)),
+            _ => None,
+        }
+    }
+
+    fn decode_into(bytes: &[u8], data: &mut [Self]) {
+        for (x, out) in bytes.chunks_exact(mem::size_of::<Self>()).zip(data) {
+            *out = f64::from_be_bytes(x.try_into().expect("element size"));
+        }
+    }
+}
 impl DataFormat for f64 {
     const MAGIC_BYTE: u8 = 0x0E;
+    const DATA_TYPE: DataType = DataType::F64;
     const READ_ELEMENT: for<'a> fn(&'a [u8]) -> IResult<'a, Self> = |x| be_f64(x);
+    const WRITE_ELEMENT: fn(&Self, &mut Vec<u8>) = |x, out| out.extend(x.to_be_bytes());
+}
+
+fn check_zero_prefix(found: u16) -> Result<(), ErrorKind> {
+    if found == 0 {
+        Ok(())
+    } else {
+        Err(ErrorKind::ZeroPrefix { found })
+    }
 }
 
-fn check_magic_byte<T: DataFormat>(b: u8) -> Result<(), Error> {
+fn check_magic_byte<T: DataFormat>(b: u8) -> Result<(), ErrorKind> {
     if b == T::MAGIC_BYTE {
         Ok(())
     } else {
-        Err(Error)
+        Err(ErrorKind::MagicByte {
+            expected: T::MAGIC_BYTE,
+            found: b,
+        })
     }
 }
 
-fn check_num_dims<const N: usize>(num_dims: u8) -> Result<usize, Error> {
+fn check_num_dims<const N: usize>(num_dims: u8) -> Result<usize, ErrorKind> {
     let num_dims = usize::from(num_dims);
     if num_dims == N {
         Ok(num_dims)
     } else {
-        Err(Error)
+        Err(ErrorKind::NumDims {
+            expected: N,
+            found: num_dims,
+        })
     }
 }
 
-fn check_dims_dimensions<const N: usize>(dims: Vec<u32>) -> Result<([u32; N], usize), Error> {
-    let dims: [u32; N] = dims.try_into().map_err(|_| Error)?;
-    let elements = dims
-        .iter()
+fn check_data_type(b: u8) -> Result<DataType, ErrorKind> {
+    DataType::from_magic_byte(b).ok_or(ErrorKind::UnknownDataType { found: b })
+}
+
+/// Returns the number of elements in an array with the given `dims`, if they fit in a `usize`
+/// along with their size in bytes.
+fn checked_elements(dims: &[u32], size: usize) -> Option<usize> {
+    dims.iter()
         .try_fold(1usize, |a, &b| a.checked_mul(usize::try_from(b).ok()?))
-        .ok_or(Error)?;
+        .filter(|elements| elements.checked_mul(size).is_some())
+}
+
+/// Checks that both the whole array and each record along its first axis can be addressed,
+/// since an array with no records can otherwise have records of any size.
+fn check_elements(dims: Vec<u32>, size: usize) -> Result<(Vec<u32>, usize), ErrorKind> {
+    let record = dims
+        .get(1..)
+        .map_or(Some(1), |dims| checked_elements(dims, size));
+    match (checked_elements(&dims, size), record) {
+        (Some(elements), Some(_)) => Ok((dims, elements)),
+        _ => Err(ErrorKind::DimsOverflow { dims }),
+    }
+}
+
+fn check_dims_dimensions<T, const N: usize>(
+    dims: Vec<u32>,
+) -> Result<([u32; N], usize), ErrorKind> {
+    let (dims, elements) = check_elements(dims, mem::size_of::<T>())?;
+    let dims: [u32; N] = dims.try_into().expect("rank checked by check_num_dims");
     Ok((dims, elements))
 }
 
-fn parse<T: DataFormat, const N: usize>(x: &[u8]) -> IResult<'_, ([u32; N], Vec<T>)> {
-    let (x, _) = tag([0u8; 2])(x)?;
+/// Checks the element type and dimensions of an array that was parsed without knowing `T` and
+/// `N`, reporting errors at the offsets of the corresponding header fields.
+fn check_type_and_rank<T: DataFormat, const N: usize>(
+    data_type: DataType,
+    dims: &[u32],
+) -> Result<[u32; N], Error> {
+    check_magic_byte::<T>(data_type.magic_byte()).map_err(|kind| Error { offset: 2, kind })?;
+    dims.try_into().map_err(|_| Error {
+        offset: 3,
+        kind: ErrorKind::NumDims {
+            expected: N,
+            found: dims.len(),
+        },
+    })
+}
+
+fn check_eof(found: usize) -> Result<(), ErrorKind> {
+    if found == 0 {
+        Ok(())
+    } else {
+        Err(ErrorKind::TrailingBytes { found })
+    }
+}
+
+/// Fails without consuming anything unless at least `expected` bytes remain.
+fn ensure<'a>(expected: usize) -> impl FnMut(&'a [u8]) -> HResult<'a, ()> {
+    map_res(rest_len, move |found| {
+        if found >= expected {
+            Ok(())
+        } else {
+            Err(ErrorKind::Truncated { expected, found })
+        }
+    })
+}
+
+/// Decodes `bytes`, which holds a whole number of elements, onto the end of `data`.
+#[cfg(feature = "std")]
+fn decode_elements<T: DataFormat>(bytes: &[u8], data: &mut Vec<T>) {
+    debug_assert_eq!(bytes.len() % mem::size_of::<T>(), 0);
+    let start = data.len();
+    data.resize(start + bytes.len() / mem::size_of::<T>(), T::default());
+    T::decode_into(bytes, &mut data[start..]);
+}
+
+/// Decodes `payload` into `data`, which has room for exactly as many elements as it holds.
+#[cfg(not(feature = "rayon"))]
+fn decode_slice<T: DataFormat>(payload: &[u8], data: &mut [T]) {
+    T::decode_into(payload, data);
+}
+
+/// Decodes `payload` into `data`, which has room for exactly as many elements as it holds,
+/// splitting it across threads a chunk at a time.
+#[cfg(feature = "rayon")]
+fn decode_slice<T: DataFormat>(payload: &[u8], data: &mut [T]) {
+    let chunk_len = PAR_CHUNK_LEN / mem::size_of::<T>();
+    let chunks = payload.par_chunks(PAR_CHUNK_LEN);
+    chunks
+        .zip(data.par_chunks_mut(chunk_len))
+        .for_each(|(bytes, data)| T::decode_into(bytes, data));
+}
+
+/// Decodes all of `payload`, which holds exactly `elements` elements.
+fn decode_payload<T: DataFormat>(payload: &[u8], elements: usize) -> Vec<T> {
+    let mut data = vec![T::default(); elements];
+    decode_slice(payload, &mut data);
+    data
+}
+
+/// Replaces the contents of `data` with all of `payload`, decoded, reusing its allocation.
+fn decode_payload_into<T: DataFormat>(payload: &[u8], data: &mut Vec<T>) {
+    data.clear();
+    data.resize(payload.len() / mem::size_of::<T>(), T::default());
+    decode_slice(payload, data);
+}
+
+/// Takes the payload once the input is known to hold exactly that much, so that nothing is
+/// allocated for a payload that is not there.
+fn take_payload<T: DataFormat>(x: &[u8], elements: usize) -> HResult<'_, &[u8]> {
+    let payload_len = elements * mem::size_of::<T>();
+    let (x, ()) = ensure(payload_len)(x)?;
+    let (x, payload) = take(payload_len)(x)?;
+    let (x, ()) = map_res(rest_len, check_eof)(x)?;
+    Ok((x, payload))
+}
+
+fn parse_payload<T: DataFormat>(x: &[u8], elements: usize) -> HResult<'_, Vec<T>> {
+    let (x, payload) = take_payload::<T>(x, elements)?;
+    Ok((x, decode_payload(payload, elements)))
+}
+
+fn parse_typed_header<T: DataFormat, const N: usize>(x: &[u8]) -> HResult<'_, ([u32; N], usize)> {
+    let (x, ()) = ensure(4)(x)?;
+    let (x, ()) = map_res(be_u16, check_zero_prefix)(x)?;
     let (x, ()) = map_res(be_u8, check_magic_byte::<T>)(x)?;
     let (x, num_dims) = map_res(be_u8, check_num_dims::<N>)(x)?;
-    let (x, (dims, elements)) = map_res(count(be_u32, num_dims), check_dims_dimensions)(x)?;
-    let (x, data) = count(T::READ_ELEMENT, elements)(x)?;
-    let (x, _) = eof(x)?;
-    Ok((x, (dims, data)))
+    let (x, ()) = ensure(4 * num_dims)(x)?;
+    map_res(count(be_u32, num_dims), check_dims_dimensions::<T, N>)(x)
+}
+
+/// Fails as [`ParseOptions::check_header`] does, where `input` is the whole of the file.
+fn check_options<'a>(
+    input: &'a [u8],
+    options: &ParseOptions,
+    dims: &[u32],
+    data_type: DataType,
+) -> HResult<'a, ()> {
+    options.check_header(dims, data_type).map_err(|e| {
+        let input = &input[e.offset..];
+        nom::Err::Error(ParseError {
+            input,
+            kind: e.kind,
+        })
+    })?;
+    Ok((input, ()))
+}
+
+/// Parses everything but leaves the payload undecoded.
+fn parse_raw<'a, T: DataFormat, const N: usize>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> HResult<'a, ([u32; N], &'a [u8])> {
+    let (x, (dims, elements)) = parse_typed_header::<T, N>(input)?;
+    check_options(input, options, &dims, T::DATA_TYPE)?;
+    let (x, payload) = take_payload::<T>(x, elements)?;
+    Ok((x, (dims, payload)))
+}
+
+fn parse<'a, T: DataFormat, const N: usize>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> HResult<'a, ([u32; N], Vec<T>)> {
+    let (x, (dims, payload)) = parse_raw::<T, N>(input, options)?;
+    let elements = payload.len() / mem::size_of::<T>();
+    Ok((x, (dims, decode_payload(payload, elements))))
+}
+
+/// Parses as [`parse`] does, but recovers the whole records of a truncated payload and ignores
+/// bytes that follow the payload, warning about either.
+fn parse_lenient<'a, T: DataFormat, const N: usize>(
+    input: &'a [u8],
+    options: &ParseOptions,
+) -> HResult<'a, ([u32; N], Vec<T>, Vec<ParseWarning>)> {
+    let (x, (mut dims, mut elements)) = parse_typed_header::<T, N>(input)?;
+    check_options(input, options, &dims, T::DATA_TYPE)?;
+    let size = mem::size_of::<T>();
+    let mut warnings = Vec::new();
+    let truncated = x.len() < elements * size;
+    // An array without axes has no records to recover, and fails to parse as usual.
+    if truncated && N > 0 {
+        let offset = input.len() - x.len();
+        let (recovered, warning) = recover_records(&mut dims, size, offset, x.len());
+        elements = recovered;
+        warnings.push(warning);
+    }
+    let (x, ()) = ensure(elements * size)(x)?;
+    let (x, payload) = take(elements * size)(x)?;
+    if !truncated && !x.is_empty() {
+        let offset = input.len() - x.len();
+        let found = x.len();
+        warnings.push(ParseWarning::TrailingBytes { offset, found });
+    }
+    let data = decode_payload(payload, elements);
+    Ok((x, (dims, data, warnings)))
+}
+
+fn parse_header(x: &[u8]) -> HResult<'_, IdxHeader> {
+    let (x, ()) = ensure(4)(x)?;
+    let (x, ()) = map_res(be_u16, check_zero_prefix)(x)?;
+    let (x, data_type) = map_res(be_u8, check_data_type)(x)?;
+    let (x, num_dims) = map(be_u8, usize::from)(x)?;
+    let (x, ()) = ensure(4 * num_dims)(x)?;
+    let check_dims = |dims| check_elements(dims, data_type.size());
+    let (x, (dims, elements)) = map_res(count(be_u32, num_dims), check_dims)(x)?;
+    let header = IdxHeader {
+        data_type,
+        dims,
+        elements,
+    };
+    Ok((x, header))
+}
+
+fn parse_any_payload<T: DataFormat>(x: &[u8], header: IdxHeader) -> HResult<'_, AnyIdxArray> {
+    let (x, data) = parse_payload::<T>(x, header.elements)?;
+    Ok((x, T::into_any(header.dims, data)))
+}
+
+fn parse_any_array<'a>(input: &'a [u8], options: &ParseOptions) -> HResult<'a, AnyIdxArray> {
+    let (x, header) = parse_header(input)?;
+    check_options(input, options, &header.dims, header.data_type)?;
+    let parse_payload = match header.data_type {
+        DataType::U8 => parse_any_payload::<u8>,
+        DataType::I8 => parse_any_payload::<i8>,
+        DataType::I16 => parse_any_payload::<i16>,
+        DataType::I32 => parse_any_payload::<i32>,
+        DataType::F32 => parse_any_payload::<f32>,
+        DataType::F64 => parse_any_payload::<f64>,
+    };
+    parse_payload(x, header)
+}
+
+/// Runs `parser` over the whole of `input`, locating any failure by its byte offset.
+fn run_parser<'a, T>(
+    input: &'a [u8],
+    parser: impl FnOnce(&'a [u8]) -> HResult<'a, T>,
+) -> Result<T, Error> {
+    match parser(input) {
+        Ok((_, output)) => Ok(output),
+        Err(nom::Err::Error(e) | nom::Err::Failure(e)) => Err(Error {
+            offset: input.len() - e.input.len(),
+            kind: e.kind,
+        }),
+        Err(nom::Err::Incomplete(_)) => unreachable!("complete parsers never ask for more"),
+    }
+}
+
+/// Parses `input`, which is the raw contents of an `IDX` file of any element type and rank.
+///
+/// Decompresses `input` first if need be, as [`IdxArray::parse`] does.
+pub fn parse_any(input: &[u8]) -> Result<AnyIdxArray, Error> {
+    parse_any_with(input, &ParseOptions::default())
+}
+
+/// Parses `input` as [`parse_any`] does, also checking what `options` ask for.
+pub fn parse_any_with(input: &[u8], options: &ParseOptions) -> Result<AnyIdxArray, Error> {
+    let input = decompress(input, options.max_file_len(mem::size_of::<f64>()))?;
+    run_parser(&input, |x| parse_any_array(x, options))
+}
+
+/// The header of an `IDX` file, describing the array that follows it.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdxHeader {
+    data_type: DataType,
+    dims: Vec<u32>,
+    elements: usize,
+}
+
+impl IdxHeader {
+    /// The longest a header can be, which is enough to hold 255 dimensions.
+    pub const MAX_LEN: usize = 4 + 4 * u8::MAX as usize;
+
+    /// Parses the header at the start of `input`, ignoring whatever follows it.
+    ///
+    /// `input` only needs to hold the first [`IdxHeader::len`] bytes of the file, so reading
+    /// the first [`IdxHeader::MAX_LEN`] bytes is always enough.
+    pub fn parse(input: &[u8]) -> Result<Self, Error> {
+        run_parser(input, parse_header)
+    }
+
+    /// Returns the element type of the array.
+    pub fn data_type(&self) -> DataType {
+        self.data_type
+    }
+
+    /// Returns the length of each axis of the array.
+    pub fn dims(&self) -> &[u32] {
+        &self.dims
+    }
+
+    /// Returns the number of bytes taken up by the header itself.
+    #[allow(clippy::len_without_is_empty)]
+    pub fn len(&self) -> usize {
+        4 + 4 * self.dims.len()
+    }
+
+    /// Returns the number of elements in the array.
+    pub fn num_elements(&self) -> usize {
+        self.elements
+    }
+
+    /// Returns the number of records along the first axis, or `1` if the array has no axes.
+    pub fn num_records(&self) -> usize {
+        self.dims.first().map_or(1, |&records| records as usize)
+    }
+
+    /// Returns the number of elements in each record along the first axis.
+    ///
+    /// Parsing checks that a record fits in a `usize` in bytes, even if there are no records.
+    pub fn record_len(&self) -> usize {
+        self.dims.iter().skip(1).map(|&dim| dim as usize).product()
+    }
+
+    /// Returns the number of bytes taken up by the elements following the header.
+    pub fn payload_len(&self) -> usize {
+        self.elements * self.data_type.size()
+    }
+
+    /// Returns the number of bytes in a well-formed file with this header, or `None` if that
+    /// does not fit in a `usize`.
+    pub fn file_len(&self) -> Option<usize> {
+        self.payload_len().checked_add(self.len())
+    }
 }
 
 /// The array as read from an `IDX` file.
@@ -117,6 +845,31 @@ pub struct IdxArray<T, const N: usize> {
 }
 
 impl<T, const N: usize> IdxArray<T, N> {
+    /// Creates an `IdxArray` from its raw contents, laid out as in [`IdxArray::dims_data`].
+    ///
+    /// Returns `None` if `data` does not have as many elements as `dims` calls for, or if `N` is
+    /// too large to fit in an `IDX` header.
+    pub fn from_dims_data(dims: [u32; N], data: Vec<T>) -> Option<Self> {
+        let elements = dims
+            .iter()
+            .try_fold(1usize, |a, &b| a.checked_mul(usize::try_from(b).ok()?));
+        if N <= usize::from(u8::MAX) && elements == Some(data.len()) {
+            Some(IdxArray { dims, data })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the length of each axis of the array.
+    pub fn dims(&self) -> [u32; N] {
+        self.dims
+    }
+
+    /// Returns the elements of the flattened array.
+    pub fn data(&self) -> &[T] {
+        &self.data
+    }
+
     /// Returns the raw contents of the `IdxArray`.
     pub fn dims_data(self) -> ([u32; N], Vec<T>) {
         (self.dims, self.data)
@@ -127,14 +880,105 @@ impl<T: DataFormat, const N: usize> IdxArray<T, N> {
     /// Parses `input`, which is the raw contents of an `IDX` file.
     ///
     /// Assumes you know the type of the array before it's parsed (checks, but does not infer).
+    ///
+    /// With the `gzip` feature, `input` is decompressed first if it is gzipped, in which case
+    /// error offsets refer to the decompressed contents.
     pub fn parse(input: &[u8]) -> Result<Self, Error> {
-        match parse(input) {
-            Ok((_, (dims, data))) => Ok(IdxArray { dims, data }),
-            Err(_) => Err(Error),
+        Self::parse_with(input, &ParseOptions::default())
+    }
+
+    /// Parses `input` as [`IdxArray::parse`] does, also checking what `options` ask for.
+    pub fn parse_with(input: &[u8], options: &ParseOptions) -> Result<Self, Error> {
+        let input = decompress(input, options.max_file_len(mem::size_of::<T>()))?;
+        let (dims, data) = run_parser(&input, |x| parse(x, options))?;
+        Ok(IdxArray { dims, data })
+    }
+
+    /// Parses `input` as [`IdxArray::parse_with`] does, but tolerates files in the wild that
+    /// are cut short or padded at the end, returning what was wrong with them alongside.
+    ///
+    /// If the payload is truncated, the first axis is shortened to the records that were read
+    /// whole. Bytes that follow the payload are ignored. Every other check still fails as
+    /// usual, as does a truncated array without any axes.
+    pub fn parse_lenient(
+        input: &[u8],
+        options: &ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning>), Error> {
+        let input = decompress(input, options.max_file_len(mem::size_of::<T>()))?;
+        let (dims, data, warnings) = run_parser(&input, |x| parse_lenient(x, options))?;
+        Ok((IdxArray { dims, data }, warnings))
+    }
+
+    /// Parses `input` as [`IdxArray::parse`] does, but into `self`, reusing the allocation of
+    /// its data if it is large enough.
+    ///
+    /// `self` is left unchanged if `input` fails to parse.
+    pub fn parse_into(&mut self, input: &[u8]) -> Result<(), Error> {
+        let input = decompress(input, None)?;
+        let options = ParseOptions::default();
+        let (dims, payload) = run_parser(&input, |x| parse_raw::<T, N>(x, &options))?;
+        decode_payload_into(payload, &mut self.data);
+        self.dims = dims;
+        Ok(())
+    }
+}
+
+/// An array read from an `IDX` file whose element type and rank were inferred from its header.
+///
+/// Each variant holds the dimensions and the data, as returned by [`IdxArray::dims_data`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnyIdxArray {
+    U8(Vec<u32>, Vec<u8>),
+    I8(Vec<u32>, Vec<i8>),
+    I16(Vec<u32>, Vec<i16>),
+    I32(Vec<u32>, Vec<i32>),
+    F32(Vec<u32>, Vec<f32>),
+    F64(Vec<u32>, Vec<f64>),
+}
+
+impl AnyIdxArray {
+    /// Returns the element type of the array.
+    pub fn data_type(&self) -> DataType {
+        match self {
+            AnyIdxArray::U8(..) => DataType::U8,
+            AnyIdxArray::I8(..) => DataType::I8,
+            AnyIdxArray::I16(..) => DataType::I16,
+            AnyIdxArray::I32(..) => DataType::I32,
+            AnyIdxArray::F32(..) => DataType::F32,
+            AnyIdxArray::F64(..) => DataType::F64,
+        }
+    }
+
+    /// Returns the length of each axis of the array.
+    pub fn dims(&self) -> &[u32] {
+        match self {
+            AnyIdxArray::U8(dims, _)
+            | AnyIdxArray::I8(dims, _)
+            | AnyIdxArray::I16(dims, _)
+            | AnyIdxArray::I32(dims, _)
+            | AnyIdxArray::F32(dims, _)
+            | AnyIdxArray::F64(dims, _) => dims,
         }
     }
 }
 
+impl<T: DataFormat, const N: usize> From<IdxArray<T, N>> for AnyIdxArray {
+    fn from(array: IdxArray<T, N>) -> Self {
+        T::into_any(array.dims.to_vec(), array.data)
+    }
+}
+
+/// Fails with the same error that [`IdxArray::parse`] would have given for the original file.
+impl<T: DataFormat, const N: usize> TryFrom<AnyIdxArray> for IdxArray<T, N> {
+    type Error = Error;
+
+    fn try_from(array: AnyIdxArray) -> Result<Self, Error> {
+        let dims = check_type_and_rank::<T, N>(array.data_type(), array.dims())?;
+        let (_, data) = T::from_any(array).expect("element type checked");
+        Ok(IdxArray { dims, data })
+    }
+}
+
 impl<T> IdxArray<T, 1> {
     /// Returns the contents of this array as a linear sequence.
     pub fn into_sequence(self) -> Vec<T> {
@@ -142,13 +986,17 @@ impl<T> IdxArray<T, 1> {
     }
 }
 
+#[cfg(feature = "image")]
 impl IdxArray<u8, 3> {
     /// Returns the sequence of greyscale images, assuming that `self` is a sequence
     /// of images over its first axis.
     pub fn as_gray_image_sequence(&self) -> Vec<GrayImage> {
         let [_, height, width] = self.dims;
-        self.data
-            .chunks_exact((width * height) as usize)
+        #[cfg(not(feature = "rayon"))]
+        let images = self.data.chunks_exact((width * height) as usize);
+        #[cfg(feature = "rayon")]
+        let images = self.data.par_chunks_exact((width * height) as usize);
+        images
             .map(|buf| GrayImage::from_raw(width, height, buf.to_vec()).unwrap())
             .collect()
     }
@@ -156,7 +1004,12 @@ impl IdxArray<u8, 3> {
 
 #[cfg(test)]
 mod tests {
+    use super::parse_any;
+    use super::AnyIdxArray;
+    use super::DataType;
+    use super::ErrorKind;
     use super::IdxArray;
+    use super::IdxHeader;
     use std::fs;
 
     #[test]
@@ -179,6 +1032,7 @@ mod tests {
         assert!((0u8..=9).all(|e| x.contains(&e)));
     }
 
+    #[cfg(feature = "image")]
     #[test]
     fn test_t10k_idx3_ubyte() {
         let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
@@ -187,6 +1041,7 @@ mod tests {
         assert_eq!(x.len(), 10_000);
     }
 
+    #[cfg(feature = "image")]
     #[test]
     fn test_train_idx3_ubyte() {
         let x = fs::read("data/train-images.idx3-ubyte").expect("idx file");
@@ -194,4 +1049,141 @@ mod tests {
         let x = x.as_gray_image_sequence();
         assert_eq!(x.len(), 60_000);
     }
+
+    #[test]
+    fn test_magic_byte_error() {
+        let x = [0, 0, 0x0D, 1, 0, 0, 0, 1, 0, 0, 0, 0];
+        let e = IdxArray::<u8, 1>::parse(&x).unwrap_err();
+        assert_eq!(e.offset(), 2);
+        assert_eq!(
+            e.kind(),
+            &ErrorKind::MagicByte {
+                expected: 0x08,
+                found: 0x0D
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "IDX parse error at byte 2: expected magic 0x08 (u8), found 0x0D (f32)"
+        );
+    }
+
+    #[test]
+    fn test_structural_errors() {
+        let e = IdxArray::<u8, 1>::parse(&[0, 1, 0x08, 1]).unwrap_err();
+        assert_eq!(
+            (e.offset(), e.kind()),
+            (0, &ErrorKind::ZeroPrefix { found: 1 })
+        );
+
+        let e = IdxArray::<u8, 2>::parse(&[0, 0, 0x08, 1, 0, 0, 0, 0]).unwrap_err();
+        let kind = ErrorKind::NumDims {
+            expected: 2,
+            found: 1,
+        };
+        assert_eq!((e.offset(), e.kind()), (3, &kind));
+
+        let e = IdxArray::<u8, 1>::parse(&[0, 0, 0x08, 1, 0, 0, 0, 3, 7, 7]).unwrap_err();
+        let kind = ErrorKind::Truncated {
+            expected: 3,
+            found: 2,
+        };
+        assert_eq!((e.offset(), e.kind()), (8, &kind));
+
+        let e = IdxArray::<u8, 1>::parse(&[0, 0, 0x08, 1, 0, 0, 0, 1, 7, 7]).unwrap_err();
+        assert_eq!(
+            (e.offset(), e.kind()),
+            (9, &ErrorKind::TrailingBytes { found: 1 })
+        );
+    }
+
+    #[test]
+    fn test_parse_any() {
+        let x = [0, 0, 0x0B, 2, 0, 0, 0, 1, 0, 0, 0, 2, 0xFF, 0xFE, 0, 3];
+        let any = parse_any(&x).expect("parse index");
+        assert_eq!(any, AnyIdxArray::I16(vec![1, 2], vec![-2, 3]));
+        assert_eq!(any.data_type(), DataType::I16);
+
+        let e = IdxArray::<i16, 1>::try_from(any.clone()).unwrap_err();
+        assert_eq!(e, IdxArray::<i16, 1>::parse(&x).unwrap_err());
+        let e = IdxArray::<u8, 2>::try_from(any.clone()).unwrap_err();
+        assert_eq!(e, IdxArray::<u8, 2>::parse(&x).unwrap_err());
+        let array = IdxArray::<i16, 2>::try_from(any).expect("convert");
+        assert_eq!(array.dims_data(), ([1, 2], vec![-2, 3]));
+
+        let e = parse_any(&[0, 0, 0x0A, 0]).unwrap_err();
+        assert_eq!(
+            (e.offset(), e.kind()),
+            (2, &ErrorKind::UnknownDataType { found: 0x0A })
+        );
+    }
+
+    #[test]
+    fn test_header() {
+        let x = fs::read("data/train-images.idx3-ubyte").expect("idx file");
+        let header = IdxHeader::parse(&x[..IdxHeader::MAX_LEN]).expect("parse header");
+        assert_eq!(header.data_type(), DataType::U8);
+        assert_eq!(header.dims(), [60_000, 28, 28]);
+        assert_eq!(header.len(), 16);
+        assert_eq!(header.num_elements(), 60_000 * 28 * 28);
+        assert_eq!(header.payload_len(), 60_000 * 28 * 28);
+        assert_eq!(header.file_len(), Some(x.len()));
+
+        let e = IdxHeader::parse(&x[..10]).unwrap_err();
+        let kind = ErrorKind::Truncated {
+            expected: 12,
+            found: 6,
+        };
+        assert_eq!((e.offset(), e.kind()), (4, &kind));
+    }
+
+    #[test]
+    fn test_decode_chunks() {
+        // Long enough to span many chunks, whether decoded serially or in parallel.
+        let data: Vec<f64> = (0..100_000).map(|i| f64::from(i) - 0.5).collect();
+        let array = IdxArray::from_dims_data([100, 1_000], data).expect("array");
+        let x = array.to_bytes();
+        assert_eq!(IdxArray::parse(&x).as_ref(), Ok(&array));
+        #[cfg(feature = "std")]
+        assert_eq!(IdxArray::read_from(&x[..]).expect("read index"), array);
+    }
+
+    #[cfg(feature = "image")]
+    #[test]
+    fn test_gray_image_chunks() {
+        let data: Vec<u8> = (0..100_000).map(|i| i as u8).collect();
+        let array = IdxArray::from_dims_data([1_000, 10, 10], data).expect("array");
+        let images = array.as_gray_image_sequence();
+        assert_eq!(images.len(), 1_000);
+        assert!(images
+            .iter()
+            .zip(array.data().chunks_exact(100))
+            .all(|(image, pixels)| image.as_raw() == pixels));
+    }
+
+    #[test]
+    fn test_parse_into() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let mut array = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        let small = IdxArray::from_dims_data([2, 28, 28], vec![7; 2 * 28 * 28]).expect("array");
+        let ptr = array.data().as_ptr();
+        array.parse_into(&small.to_bytes()).expect("parse index");
+        assert_eq!(array, small);
+        assert_eq!(array.data().as_ptr(), ptr);
+
+        let e = array.parse_into(&x[..x.len() - 1]).unwrap_err();
+        assert_eq!(e, IdxArray::<u8, 3>::parse(&x[..x.len() - 1]).unwrap_err());
+        assert_eq!(array, small);
+        let labels = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let e = array.parse_into(&labels).unwrap_err();
+        let kind = ErrorKind::NumDims {
+            expected: 3,
+            found: 1,
+        };
+        assert_eq!((e.offset(), e.kind()), (3, &kind));
+        assert_eq!(array, small);
+
+        array.parse_into(&x).expect("parse index");
+        assert_eq!(array, IdxArray::<u8, 3>::parse(&x).expect("parse index"));
+    }
 }
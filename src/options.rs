@@ -0,0 +1,355 @@
+//! Options that tighten what parsing accepts beyond the checks made on every file.
+
+use crate::DataType;
+use crate::Error;
+use crate::ErrorKind;
+use crate::IdxHeader;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// A constraint on the length of one axis of an array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DimConstraint {
+    /// Any length.
+    Any,
+    /// Exactly this length.
+    Exact(u32),
+    /// A length from `min` to `max`, inclusive.
+    Range { min: u32, max: u32 },
+}
+
+impl DimConstraint {
+    /// Returns `true` if an axis of length `dim` meets the constraint.
+    pub fn matches(self, dim: u32) -> bool {
+        match self {
+            DimConstraint::Any => true,
+            DimConstraint::Exact(expected) => dim == expected,
+            DimConstraint::Range { min, max } => (min..=max).contains(&dim),
+        }
+    }
+}
+
+/// Formats the constraint as `_`, `28` or `1..=32`.
+impl fmt::Display for DimConstraint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DimConstraint::Any => f.write_str("_"),
+            DimConstraint::Exact(expected) => write!(f, "{expected}"),
+            DimConstraint::Range { min, max } => write!(f, "{min}..={max}"),
+        }
+    }
+}
+
+/// Something wrong with an `IDX` file that lenient parsing overlooked, such as with
+/// [`IdxArray::parse_lenient`].
+///
+/// [`IdxArray::parse_lenient`]: crate::IdxArray::parse_lenient
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ParseWarning {
+    /// The input continues for `found` bytes after the end of the payload at `offset`, which
+    /// were ignored.
+    TrailingBytes { offset: usize, found: usize },
+    /// The input ends partway through the payload, so only the first `found` of the `expected`
+    /// records were recovered. The first missing record would have started at `offset`.
+    MissingRecords {
+        offset: usize,
+        expected: usize,
+        found: usize,
+    },
+}
+
+impl fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseWarning::TrailingBytes { offset, found } => {
+                write!(f, "ignored {found} trailing bytes at byte {offset}")
+            }
+            ParseWarning::MissingRecords {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "input ends at byte {offset}, missing {} of {expected} records",
+                expected - found,
+            ),
+        }
+    }
+}
+
+/// Shrinks the first axis of `dims` to the number of whole records in the `available` bytes of
+/// a payload that starts at `offset` and should be longer, returning how many elements of
+/// `size` bytes those records hold, and the warning to give about the rest.
+///
+/// `dims` must have a first axis.
+pub(crate) fn recover_records(
+    dims: &mut [u32],
+    size: usize,
+    offset: usize,
+    available: usize,
+) -> (usize, ParseWarning) {
+    let expected = dims[0] as usize;
+    let record_len: usize = dims[1..].iter().map(|&dim| dim as usize).product();
+    // Records are not empty, or the payload would be too.
+    let record_bytes = record_len * size;
+    let found = available / record_bytes;
+    dims[0] = found as u32;
+    let warning = ParseWarning::MissingRecords {
+        offset: offset + found * record_bytes,
+        expected,
+        found,
+    };
+    (found * record_len, warning)
+}
+
+/// Options for parsing and reading `IDX` files, for use with [`IdxArray::parse_with`],
+/// [`IdxArray::read_from_with`] and [`parse_any_with`], as well as by lenient parsing.
+///
+/// The default options accept everything that [`IdxArray::parse`] does. Setting a limit on the
+/// size of the array makes it safe to parse untrusted files, as the limit is checked against
+/// the header before anything is allocated for the payload.
+///
+/// [`IdxArray::parse_with`]: crate::IdxArray::parse_with
+/// [`IdxArray::read_from_with`]: crate::IdxArray::read_from_with
+/// [`IdxArray::parse`]: crate::IdxArray::parse
+/// [`parse_any_with`]: crate::parse_any_with
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct ParseOptions {
+    dims: Option<Vec<DimConstraint>>,
+    max_elements: Option<usize>,
+    max_bytes: Option<usize>,
+}
+
+impl ParseOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requires the array to have one axis per constraint, each of which meets its constraint,
+    /// such as `[Any, Exact(28), Exact(28)]` for a sequence of 28x28 images.
+    pub fn dims(mut self, dims: impl Into<Vec<DimConstraint>>) -> Self {
+        self.dims = Some(dims.into());
+        self
+    }
+
+    /// Limits the array to at most `max_elements` elements.
+    pub fn max_elements(mut self, max_elements: usize) -> Self {
+        self.max_elements = Some(max_elements);
+        self
+    }
+
+    /// Limits the payload of the array to at most `max_bytes` bytes, which is also how much
+    /// memory its elements take up once decoded.
+    ///
+    /// Compressed input is decompressed no further than this limit allows.
+    pub fn max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Returns the longest that the file can be, uncompressed, without its array going over
+    /// the limits, if there are any, given that each element is at most `size` bytes.
+    pub(crate) fn max_file_len(&self, size: usize) -> Option<usize> {
+        let max_bytes = self.max_elements.map(|max| max.saturating_mul(size));
+        let max_bytes = match (max_bytes, self.max_bytes) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        max_bytes.map(|max| max.saturating_add(IdxHeader::MAX_LEN))
+    }
+
+    /// Checks the dimensions of an array of `data_type` against the options, reporting a
+    /// failure at the offset of the header field that holds the number of dimensions, or the
+    /// offending axis, or the first axis if the array as a whole is too large.
+    ///
+    /// The total size of the array must already be known to fit in a `usize`.
+    pub(crate) fn check_header(&self, dims: &[u32], data_type: DataType) -> Result<(), Error> {
+        self.check_dims(dims)?;
+        let elements: usize = dims.iter().map(|&dim| dim as usize).product();
+        let bytes = elements * data_type.size();
+        let kind = match (self.max_elements, self.max_bytes) {
+            (Some(limit), _) if elements > limit => ErrorKind::TooManyElements {
+                limit,
+                found: elements,
+            },
+            (_, Some(limit)) if bytes > limit => ErrorKind::TooManyBytes {
+                limit,
+                found: bytes,
+            },
+            _ => return Ok(()),
+        };
+        Err(Error { offset: 4, kind })
+    }
+
+    fn check_dims(&self, dims: &[u32]) -> Result<(), Error> {
+        let Some(constraints) = &self.dims else {
+            return Ok(());
+        };
+        if constraints.len() != dims.len() {
+            let kind = ErrorKind::NumDims {
+                expected: constraints.len(),
+                found: dims.len(),
+            };
+            return Err(Error { offset: 3, kind });
+        }
+        let mut axes = constraints.iter().zip(dims).enumerate();
+        match axes.find(|(_, (constraint, &dim))| !constraint.matches(dim)) {
+            Some((axis, (&expected, &found))) => Err(Error {
+                offset: 4 + 4 * axis,
+                kind: ErrorKind::DimMismatch {
+                    axis,
+                    expected,
+                    found,
+                },
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::DimConstraint::Any;
+    use super::DimConstraint::Exact;
+    use super::DimConstraint::Range;
+    use super::ParseOptions;
+    use super::ParseWarning;
+    use crate::parse_any_with;
+    use crate::ErrorKind;
+    use crate::IdxArray;
+    use crate::ReadError;
+    use std::fs;
+
+    #[test]
+    fn test_dims() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let options = ParseOptions::new().dims([Any, Exact(28), Range { min: 1, max: 28 }]);
+        let array = IdxArray::<u8, 3>::parse_with(&x, &options).expect("parse index");
+        assert_eq!(array.dims(), [10_000, 28, 28]);
+
+        let options = ParseOptions::new().dims([Any, Exact(32), Exact(32)]);
+        let e = IdxArray::<u8, 3>::parse_with(&x, &options).unwrap_err();
+        let kind = ErrorKind::DimMismatch {
+            axis: 1,
+            expected: Exact(32),
+            found: 28,
+        };
+        assert_eq!((e.offset(), e.kind()), (8, &kind));
+        assert_eq!(
+            e.to_string(),
+            "IDX parse error at byte 8: expected axis 1 to be 32, found 28"
+        );
+        match IdxArray::<u8, 3>::read_from_with(&x[..], &options) {
+            Err(ReadError::Parse(read)) => assert_eq!(read, e),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+
+        let options = ParseOptions::new().dims([Range { min: 1, max: 9_999 }, Any, Any]);
+        let e = parse_any_with(&x, &options).unwrap_err();
+        assert_eq!(e.offset(), 4);
+
+        let options = ParseOptions::new().dims([Any, Any]);
+        let e = parse_any_with(&x, &options).unwrap_err();
+        let kind = ErrorKind::NumDims {
+            expected: 2,
+            found: 3,
+        };
+        assert_eq!((e.offset(), e.kind()), (3, &kind));
+    }
+
+    #[test]
+    fn test_limits() {
+        // A header declaring 2^32 - 1 elements, followed by hardly any of them.
+        let x = [0, 0, 0x0D, 1, 0xFF, 0xFF, 0xFF, 0xFF, 0, 0, 0, 0];
+        let options = ParseOptions::new().max_elements(1 << 20);
+        let e = IdxArray::<f32, 1>::parse_with(&x, &options).unwrap_err();
+        let kind = ErrorKind::TooManyElements {
+            limit: 1 << 20,
+            found: u32::MAX as usize,
+        };
+        assert_eq!((e.offset(), e.kind()), (4, &kind));
+        let e = IdxArray::<f32, 1>::read_from_with(&x[..], &options).unwrap_err();
+        assert_eq!(e.to_string(), format!("IDX parse error at byte 4: {kind}"));
+
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let options = ParseOptions::new().max_bytes(10_000);
+        IdxArray::<u8, 1>::parse_with(&x, &options).expect("parse index");
+        let options = ParseOptions::new().max_bytes(9_999);
+        let e = parse_any_with(&x, &options).unwrap_err();
+        let kind = ErrorKind::TooManyBytes {
+            limit: 9_999,
+            found: 10_000,
+        };
+        assert_eq!((e.offset(), e.kind()), (4, &kind));
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_decompression_limit() {
+        let array = IdxArray::from_dims_data([1 << 20], vec![0u8; 1 << 20]).expect("array");
+        let gz = array.write_gzip_to(Vec::new()).expect("compress");
+        let options = ParseOptions::new().max_elements(1 << 20);
+        assert_eq!(IdxArray::parse_with(&gz, &options).as_ref(), Ok(&array));
+        let options = ParseOptions::new().max_elements(1 << 10);
+        let e = IdxArray::<u8, 1>::parse_with(&gz, &options).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::Decompression { .. }));
+        let options = ParseOptions::new().max_bytes(usize::MAX);
+        assert_eq!(IdxArray::parse_with(&gz, &options).as_ref(), Ok(&array));
+        let options = ParseOptions::new().max_elements(usize::MAX);
+        assert_eq!(IdxArray::parse_with(&gz, &options).as_ref(), Ok(&array));
+    }
+
+    #[test]
+    fn test_lenient() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let options = ParseOptions::new();
+        let array = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        let (lenient, warnings) = IdxArray::parse_lenient(&x, &options).expect("parse index");
+        assert_eq!((&lenient, warnings), (&array, vec![]));
+
+        let mut padded = x.clone();
+        padded.extend([0; 3]);
+        let warning = ParseWarning::TrailingBytes {
+            offset: x.len(),
+            found: 3,
+        };
+        let parsed = IdxArray::<u8, 3>::parse_lenient(&padded, &options).expect("parse index");
+        assert_eq!(parsed, (array.clone(), vec![warning.clone()]));
+        let read = IdxArray::<u8, 3>::read_from_lenient(&padded[..], &options).expect("read");
+        assert_eq!(read, (array.clone(), vec![warning]));
+
+        // Cut off partway through the image at index 2.
+        let truncated = &x[..16 + 2 * 28 * 28 + 100];
+        assert!(IdxArray::<u8, 3>::parse(truncated).is_err());
+        let warning = ParseWarning::MissingRecords {
+            offset: 16 + 2 * 28 * 28,
+            expected: 10_000,
+            found: 2,
+        };
+        assert_eq!(
+            warning.to_string(),
+            "input ends at byte 1584, missing 9998 of 10000 records"
+        );
+        let (parsed, warnings) =
+            IdxArray::<u8, 3>::parse_lenient(truncated, &options).expect("parse index");
+        assert_eq!(parsed.dims(), [2, 28, 28]);
+        assert_eq!(parsed.data(), &array.data()[..2 * 28 * 28]);
+        let read = IdxArray::<u8, 3>::read_from_lenient(truncated, &options).expect("read");
+        assert_eq!(read, (parsed, warnings.clone()));
+        assert_eq!(warnings, [warning]);
+
+        // Other checks are as strict as ever.
+        let options = ParseOptions::new().dims([Any, Exact(32), Exact(32)]);
+        let e = IdxArray::<u8, 3>::parse_lenient(truncated, &options).unwrap_err();
+        assert_eq!(e.offset(), 8);
+        let options = ParseOptions::new();
+        let e = IdxArray::<f32, 0>::parse_lenient(&[0, 0, 0x0D, 0, 1, 2], &options).unwrap_err();
+        let kind = ErrorKind::Truncated {
+            expected: 4,
+            found: 2,
+        };
+        assert_eq!((e.offset(), e.kind()), (4, &kind));
+    }
+}
@@ -0,0 +1,416 @@
+//! Reading `IDX` files incrementally from a [`Read`] stream, rather than from a slice holding
+//! the whole file.
+
+use crate::check_data_type;
+use crate::check_magic_byte;
+use crate::check_num_dims;
+use crate::check_type_and_rank;
+use crate::check_zero_prefix;
+use crate::compress::Decoder;
+use crate::decode_elements;
+use crate::options::recover_records;
+use crate::DataFormat;
+use crate::Error;
+use crate::ErrorKind;
+use crate::IdxArray;
+use crate::IdxHeader;
+use crate::ParseOptions;
+use crate::ParseWarning;
+use std::error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::io::Read;
+use std::mem;
+use std::path::Path;
+
+/// Number of payload bytes decoded at a time.
+const CHUNK_LEN: usize = 1 << 16;
+
+/// Error from reading an `IDX` file from a stream.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ReadError {
+    /// The underlying reader failed.
+    Io(io::Error),
+    /// The bytes read are not a valid `IDX` file.
+    Parse(Error),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReadError::Io(e) => write!(f, "IDX read error: {e}"),
+            ReadError::Parse(e) => e.fmt(f),
+        }
+    }
+}
+
+impl error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            ReadError::Io(e) => Some(e),
+            ReadError::Parse(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        ReadError::Io(e)
+    }
+}
+
+impl From<Error> for ReadError {
+    fn from(e: Error) -> Self {
+        ReadError::Parse(e)
+    }
+}
+
+/// Reads until `buf` is full or the stream ends, returning how many bytes were read.
+pub(crate) fn read_fully(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut found = 0;
+    while found < buf.len() {
+        match reader.read(&mut buf[found..]) {
+            Ok(0) => break,
+            Ok(n) => found += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(found)
+}
+
+/// Replaces the contents of `buf` with up to `len` bytes, growing it as they arrive rather than
+/// trusting `len` with its capacity, and returns how many bytes were read.
+pub(crate) fn read_up_to(
+    reader: &mut impl Read,
+    len: usize,
+    buf: &mut Vec<u8>,
+) -> io::Result<usize> {
+    buf.clear();
+    reader.take(len as u64).read_to_end(buf)
+}
+
+/// Fills `buf`, which starts `offset` bytes into the file, failing if the stream ends first.
+pub(crate) fn read_exact_at(
+    reader: &mut impl Read,
+    buf: &mut [u8],
+    offset: usize,
+) -> Result<(), ReadError> {
+    let found = read_fully(reader, buf)?;
+    if found == buf.len() {
+        Ok(())
+    } else {
+        let expected = buf.len();
+        let kind = ErrorKind::Truncated { expected, found };
+        Err(Error { offset, kind }.into())
+    }
+}
+
+/// Reads and decodes as much of the payload following `header` as `reader` holds, a chunk at a
+/// time, replacing the contents of `data` with the whole elements that were read. Returns the
+/// number of bytes read.
+fn read_available<T: DataFormat>(
+    reader: &mut impl Read,
+    header: &IdxHeader,
+    data: &mut Vec<T>,
+) -> io::Result<usize> {
+    debug_assert_eq!(CHUNK_LEN % mem::size_of::<T>(), 0);
+    let payload_len = header.payload_len();
+    // Grows as the payload arrives, rather than trusting the header with its capacity.
+    data.clear();
+    let mut buf = vec![0; CHUNK_LEN.min(payload_len)];
+    let mut done = 0;
+    while done < payload_len {
+        let chunk = &mut buf[..CHUNK_LEN.min(payload_len - done)];
+        let found = read_fully(reader, chunk)?;
+        let whole = found - found % mem::size_of::<T>();
+        decode_elements(&chunk[..whole], data);
+        done += found;
+        if found < chunk.len() {
+            break;
+        }
+    }
+    Ok(done)
+}
+
+/// Describes a payload following `header` of which only `found` bytes could be read.
+fn truncated(header: &IdxHeader, found: usize) -> ReadError {
+    let kind = ErrorKind::Truncated {
+        expected: header.payload_len(),
+        found,
+    };
+    let offset = header.len();
+    Error { offset, kind }.into()
+}
+
+/// Reads and decodes the payload following `header` into `data`, failing if it is truncated.
+fn read_payload<T: DataFormat>(
+    reader: &mut impl Read,
+    header: &IdxHeader,
+    data: &mut Vec<T>,
+) -> Result<(), ReadError> {
+    let found = read_available(reader, header, data)?;
+    if found < header.payload_len() {
+        return Err(truncated(header, found));
+    }
+    Ok(())
+}
+
+/// Reads the rest of `reader`, returning how many bytes there were.
+fn read_trailing(reader: &mut impl Read) -> io::Result<usize> {
+    let found = io::copy(reader, &mut io::sink())?;
+    Ok(usize::try_from(found).unwrap_or(usize::MAX))
+}
+
+/// Fails unless `reader`, which is `offset` bytes into the file, has nothing left to read.
+fn read_eof(reader: &mut impl Read, offset: usize) -> Result<(), ReadError> {
+    match read_trailing(reader)? {
+        0 => Ok(()),
+        found => {
+            let kind = ErrorKind::TrailingBytes { found };
+            Err(Error { offset, kind }.into())
+        }
+    }
+}
+
+/// Reads the header from the start of an uncompressed stream, leaving it positioned at the first
+/// element.
+///
+/// The zero prefix and then `check_prefix` are checked against the first four bytes before the
+/// dimensions are read, so that errors come in the same order as from parsing.
+pub(crate) fn read_header_with(
+    reader: &mut impl Read,
+    check_prefix: impl FnOnce([u8; 4]) -> Result<(), Error>,
+) -> Result<IdxHeader, ReadError> {
+    let mut prefix = [0; 4];
+    read_exact_at(reader, &mut prefix, 0)?;
+    check_zero_prefix(u16::from_be_bytes([prefix[0], prefix[1]]))
+        .map_err(|kind| Error { offset: 0, kind })?;
+    check_prefix(prefix)?;
+    let mut buf = prefix.to_vec();
+    buf.resize(4 + 4 * usize::from(prefix[3]), 0);
+    read_exact_at(reader, &mut buf[4..], 4)?;
+    Ok(IdxHeader::parse(&buf)?)
+}
+
+/// Reads the header of a file of any element type and rank.
+pub(crate) fn read_header(reader: &mut impl Read) -> Result<IdxHeader, ReadError> {
+    read_header_with(reader, |prefix| {
+        check_data_type(prefix[2]).map_err(|kind| Error { offset: 2, kind })?;
+        Ok(())
+    })
+}
+
+/// Reads the header of a file that should hold an array of `T`s with `N` dimensions.
+fn read_array_header<T: DataFormat, const N: usize>(
+    reader: &mut impl Read,
+) -> Result<IdxHeader, ReadError> {
+    read_header_with(reader, |prefix| {
+        check_magic_byte::<T>(prefix[2]).map_err(|kind| Error { offset: 2, kind })?;
+        check_num_dims::<N>(prefix[3]).map_err(|kind| Error { offset: 3, kind })?;
+        Ok(())
+    })
+}
+
+/// Reads whatever follows the payload of `header`, warning about it rather than failing.
+fn read_lenient_eof(
+    reader: &mut impl Read,
+    header: &IdxHeader,
+) -> Result<Vec<ParseWarning>, ReadError> {
+    let offset = header.len() + header.payload_len();
+    Ok(match read_trailing(reader)? {
+        0 => vec![],
+        found => vec![ParseWarning::TrailingBytes { offset, found }],
+    })
+}
+
+impl IdxHeader {
+    /// Reads the header from the start of `reader`, decompressing it first if need be.
+    ///
+    /// An uncompressed `reader` is left positioned at the first element.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, ReadError> {
+        read_header(&mut Decoder::new(reader)?)
+    }
+}
+
+impl<T: DataFormat, const N: usize> IdxArray<T, N> {
+    /// Reads an `IDX` file from `reader`, decoding the payload as it arrives rather than
+    /// buffering the whole file first.
+    ///
+    /// Checks the same things as [`IdxArray::parse`], including that nothing follows the
+    /// payload, and likewise decompresses `reader` if it is compressed.
+    pub fn read_from<R: Read>(reader: R) -> Result<Self, ReadError> {
+        Self::read_from_with(reader, &ParseOptions::default())
+    }
+
+    /// Reads an `IDX` file from `reader` as [`IdxArray::read_from`] does, also checking what
+    /// `options` ask for before reading the payload.
+    pub fn read_from_with<R: Read>(reader: R, options: &ParseOptions) -> Result<Self, ReadError> {
+        let mut reader = Decoder::new(reader)?;
+        let header = read_array_header::<T, N>(&mut reader)?;
+        let dims = check_type_and_rank::<T, N>(header.data_type(), header.dims())?;
+        options.check_header(&dims, T::DATA_TYPE)?;
+        let mut data = Vec::new();
+        read_payload(&mut reader, &header, &mut data)?;
+        read_eof(&mut reader, header.len() + header.payload_len())?;
+        Ok(IdxArray { dims, data })
+    }
+
+    /// Reads an `IDX` file from `reader` as [`IdxArray::read_from_with`] does, but tolerates
+    /// truncated and padded files as [`IdxArray::parse_lenient`] does.
+    pub fn read_from_lenient<R: Read>(
+        reader: R,
+        options: &ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning>), ReadError> {
+        let mut reader = Decoder::new(reader)?;
+        let header = read_array_header::<T, N>(&mut reader)?;
+        let mut dims = check_type_and_rank::<T, N>(header.data_type(), header.dims())?;
+        options.check_header(&dims, T::DATA_TYPE)?;
+        let mut data = Vec::new();
+        let found = read_available(&mut reader, &header, &mut data)?;
+        if found == header.payload_len() {
+            let warnings = read_lenient_eof(&mut reader, &header)?;
+            return Ok((IdxArray { dims, data }, warnings));
+        }
+        // An array without axes has no records to recover.
+        if N == 0 {
+            return Err(truncated(&header, found));
+        }
+        let size = mem::size_of::<T>();
+        let (elements, warning) = recover_records(&mut dims, size, header.len(), found);
+        data.truncate(elements);
+        Ok((IdxArray { dims, data }, vec![warning]))
+    }
+
+    /// Reads an `IDX` file from `reader` as [`IdxArray::read_from`] does, but into `self`,
+    /// reusing the allocation of its data if it is large enough.
+    ///
+    /// If the header cannot be read or does not match `T` and `N`, `self` is left unchanged.
+    /// If reading fails after that, `self` is left empty, with every dimension `0`, or holding
+    /// the single element `T::default()` if `N` is `0`.
+    pub fn read_into<R: Read>(&mut self, reader: R) -> Result<(), ReadError> {
+        let mut reader = Decoder::new(reader)?;
+        let header = read_array_header::<T, N>(&mut reader)?;
+        let dims = check_type_and_rank::<T, N>(header.data_type(), header.dims())?;
+        let read = read_payload(&mut reader, &header, &mut self.data)
+            .and_then(|()| read_eof(&mut reader, header.len() + header.payload_len()));
+        match read {
+            Ok(()) => self.dims = dims,
+            Err(_) => {
+                self.dims = [0; N];
+                self.data.clear();
+                if N == 0 {
+                    self.data.push(T::default());
+                }
+            }
+        }
+        read
+    }
+
+    /// Reads the `IDX` file at `path`.
+    pub fn from_path(path: impl AsRef<Path>) -> Result<Self, ReadError> {
+        Self::read_from(BufReader::new(File::open(path)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ReadError;
+    use crate::IdxArray;
+    use crate::IdxHeader;
+    use std::fs;
+
+    #[test]
+    fn test_read_from_matches_parse() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let parsed = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        let read = IdxArray::<u8, 3>::read_from(&x[..]).expect("read index");
+        assert_eq!(parsed, read);
+
+        let read = IdxArray::<u8, 3>::from_path("data/t10k-images.idx3-ubyte").expect("read index");
+        assert_eq!(parsed, read);
+
+        let header = IdxHeader::read_from(&x[..]).expect("read header");
+        assert_eq!(header.dims(), [10_000, 28, 28]);
+    }
+
+    #[test]
+    fn test_read_from_errors_match_parse() {
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        for input in [
+            &x[..3],
+            &x[..6],
+            &x[..x.len() - 1],
+            &[&x[..], &[0]].concat(),
+            // Not an IDX file, which is rejected before reading 800 bytes of dimensions.
+            &[1, 2, 8, 200],
+            // An unknown type byte, which is still the wrong type for a `u8` array.
+            &[0, 0, 0x0A, 1, 0, 0, 0, 0],
+            // The wrong rank, which is rejected before reading the dimensions.
+            &[0, 0, 8, 2, 0, 0],
+        ] {
+            let parsed = IdxArray::<u8, 1>::parse(input).unwrap_err();
+            match IdxArray::<u8, 1>::read_from(input) {
+                Err(ReadError::Parse(e)) => assert_eq!(e, parsed),
+                other => panic!("expected parse error, got {other:?}"),
+            }
+        }
+        for input in [&[1, 2, 8, 200][..], &[0, 0, 0x0A, 200]] {
+            match IdxHeader::read_from(input) {
+                Err(ReadError::Parse(e)) => assert_eq!(e, IdxHeader::parse(input).unwrap_err()),
+                other => panic!("expected parse error, got {other:?}"),
+            }
+        }
+        match IdxArray::<i8, 1>::read_from(&x[..]) {
+            Err(ReadError::Parse(e)) => assert_eq!(e, IdxArray::<i8, 1>::parse(&x).unwrap_err()),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_read_into() {
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let mut array = IdxArray::from_dims_data([3], vec![1u8, 2, 3]).expect("array");
+        array.read_into(&x[..]).expect("read index");
+        assert_eq!(array, IdxArray::<u8, 1>::parse(&x).expect("parse index"));
+
+        let small = IdxArray::from_dims_data([2], vec![4u8, 5]).expect("array");
+        let ptr = array.data().as_ptr();
+        array.read_into(&small.to_bytes()[..]).expect("read index");
+        assert_eq!(array, small);
+        assert_eq!(array.data().as_ptr(), ptr);
+
+        match array.read_into(&[0, 0, 8, 2][..]) {
+            Err(ReadError::Parse(e)) => assert_eq!(e.offset(), 3),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+        assert_eq!(array, small);
+
+        match array.read_into(&x[..x.len() - 1]) {
+            Err(ReadError::Parse(e)) => assert_eq!(e.offset(), 8),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+        assert_eq!(array.dims(), [0]);
+        assert!(array.data().is_empty());
+
+        let mut trailing = small.to_bytes();
+        trailing.push(0);
+        array.read_into(&x[..]).expect("read index");
+        match array.read_into(&trailing[..]) {
+            Err(ReadError::Parse(e)) => assert_eq!(e.offset(), 10),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+        assert_eq!(array.dims(), [0]);
+        assert!(array.data().is_empty());
+
+        let mut scalar = IdxArray::from_dims_data([], vec![7u8]).expect("array");
+        match scalar.read_into(&[0, 0, 8, 0][..]) {
+            Err(ReadError::Parse(e)) => assert_eq!(e.offset(), 4),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+        assert_eq!(scalar.data(), [0]);
+    }
+}
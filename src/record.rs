@@ -0,0 +1,421 @@
+//! Reading `IDX` files one record at a time, where a record is everything at a single index
+//! along the first axis.
+
+use crate::check_magic_byte;
+use crate::compress::Decoder;
+use crate::decode_elements;
+use crate::read::read_header_with;
+use crate::read::read_up_to;
+use crate::DataFormat;
+use crate::Error;
+use crate::ErrorKind;
+use crate::IdxHeader;
+use crate::ReadError;
+use std::fs::File;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::iter::FusedIterator;
+use std::marker::PhantomData;
+use std::path::Path;
+
+/// Reads the header, checking that it holds elements of type `T` and has a first axis to split
+/// records along.
+fn read_records_header<T: DataFormat>(reader: &mut impl Read) -> Result<IdxHeader, ReadError> {
+    read_header_with(reader, |prefix| {
+        check_magic_byte::<T>(prefix[2]).map_err(|kind| Error { offset: 2, kind })?;
+        if prefix[3] == 0 {
+            let kind = ErrorKind::NumDims {
+                expected: 1,
+                found: 0,
+            };
+            return Err(Error { offset: 3, kind });
+        }
+        Ok(())
+    })
+}
+
+/// Reads the records of an `IDX` file lazily from a stream.
+///
+/// Each record is one `[h, w]` image of an `idx3` image file, one label of an `idx1` label
+/// file, and so on. Reading stops after the last record without checking what follows it.
+#[derive(Debug)]
+pub struct IdxRecordReader<T, R> {
+    reader: Decoder<R>,
+    header: IdxHeader,
+    next: usize,
+    record_bytes: usize,
+    buf: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+impl<T: DataFormat, R: Read> IdxRecordReader<T, R> {
+    /// Reads the header from the start of `reader`, checking that it holds elements of type `T`.
+    ///
+    /// Decompresses `reader` if it is compressed.
+    pub fn new(reader: R) -> Result<Self, ReadError> {
+        let mut reader = Decoder::new(reader)?;
+        let header = read_records_header::<T>(&mut reader)?;
+        let record_bytes = header.record_len() * header.data_type().size();
+        Ok(IdxRecordReader {
+            reader,
+            header,
+            next: 0,
+            record_bytes,
+            buf: Vec::new(),
+            element: PhantomData,
+        })
+    }
+
+    /// Returns the header of the file being read.
+    pub fn header(&self) -> &IdxHeader {
+        &self.header
+    }
+
+    /// Returns the dimensions of each record, which are those of the file minus the first.
+    pub fn record_dims(&self) -> &[u32] {
+        &self.header.dims()[1..]
+    }
+
+    /// Returns the number of records that have not been read yet.
+    pub fn remaining(&self) -> usize {
+        self.header.num_records() - self.next
+    }
+
+    /// Replaces the contents of `record` with the next record, reusing its allocation.
+    ///
+    /// Returns `false`, leaving `record` empty, once every record has been read or reading one
+    /// has failed.
+    pub fn read_record(&mut self, record: &mut Vec<T>) -> Result<bool, ReadError> {
+        record.clear();
+        if self.remaining() == 0 {
+            return Ok(false);
+        }
+        // Part of the record may have been consumed before an error, so nothing after it can be
+        // read in step with the records.
+        let found = match read_up_to(&mut self.reader, self.record_bytes, &mut self.buf) {
+            Ok(found) => found,
+            Err(e) => {
+                self.next = self.header.num_records();
+                return Err(e.into());
+            }
+        };
+        if found < self.record_bytes {
+            let offset = self.header.len() + self.next * self.record_bytes;
+            let kind = ErrorKind::Truncated {
+                expected: self.record_bytes,
+                found,
+            };
+            self.next = self.header.num_records();
+            return Err(Error { offset, kind }.into());
+        }
+        decode_elements(&self.buf, record);
+        self.next += 1;
+        Ok(true)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader.into_inner()
+    }
+}
+
+/// Stops after the first error.
+impl<T: DataFormat, R: Read> Iterator for IdxRecordReader<T, R> {
+    type Item = Result<Vec<T>, ReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = Vec::new();
+        match self.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining(), Some(self.remaining()))
+    }
+}
+
+impl<T: DataFormat, R: Read> ExactSizeIterator for IdxRecordReader<T, R> {}
+
+impl<T: DataFormat, R: Read> FusedIterator for IdxRecordReader<T, R> {}
+
+/// Reads individual records of an `IDX` file in any order, seeking straight to each one.
+///
+/// Since every record has the same size, record `i` starts `header.len() + i * record_bytes`
+/// bytes into the file, so only the requested record is read.
+#[derive(Debug)]
+pub struct IdxRandomAccessReader<T, R> {
+    reader: R,
+    header: IdxHeader,
+    start: u64,
+    record_bytes: usize,
+    buf: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+impl<T: DataFormat> IdxRandomAccessReader<T, File> {
+    /// Opens the `IDX` file at `path` and reads its header.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, ReadError> {
+        Self::new(File::open(path)?)
+    }
+}
+
+impl<T: DataFormat, R: Read + Seek> IdxRandomAccessReader<T, R> {
+    /// Reads the header from the current position of `reader`, which is taken to be the start
+    /// of the file, checking that it holds elements of type `T`.
+    ///
+    /// Unlike the other readers, this cannot read compressed files.
+    pub fn new(mut reader: R) -> Result<Self, ReadError> {
+        let start = reader.stream_position()?;
+        let header = read_records_header::<T>(&mut reader)?;
+        let record_bytes = header.record_len() * header.data_type().size();
+        Ok(IdxRandomAccessReader {
+            reader,
+            header,
+            start,
+            record_bytes,
+            buf: Vec::new(),
+            element: PhantomData,
+        })
+    }
+
+    /// Returns the header of the file being read.
+    pub fn header(&self) -> &IdxHeader {
+        &self.header
+    }
+
+    /// Returns the dimensions of each record, which are those of the file minus the first.
+    pub fn record_dims(&self) -> &[u32] {
+        &self.header.dims()[1..]
+    }
+
+    /// Returns the number of records in the file.
+    pub fn len(&self) -> usize {
+        self.header.num_records()
+    }
+
+    /// Returns `true` if the file has no records.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Replaces the contents of `record` with record `index`, reusing its allocation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn read_record(&mut self, index: usize, record: &mut Vec<T>) -> Result<(), ReadError> {
+        assert!(
+            index < self.len(),
+            "record index {index} out of range for {} records",
+            self.len()
+        );
+        record.clear();
+        let offset = self.header.len() + index * self.record_bytes;
+        self.reader
+            .seek(SeekFrom::Start(self.start + offset as u64))?;
+        let found = read_up_to(&mut self.reader, self.record_bytes, &mut self.buf)?;
+        if found < self.record_bytes {
+            let kind = ErrorKind::Truncated {
+                expected: self.record_bytes,
+                found,
+            };
+            return Err(Error { offset, kind }.into());
+        }
+        decode_elements(&self.buf, record);
+        Ok(())
+    }
+
+    /// Returns record `index`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `index` is out of bounds.
+    pub fn record(&mut self, index: usize) -> Result<Vec<T>, ReadError> {
+        let mut record = Vec::new();
+        self.read_record(index, &mut record)?;
+        Ok(record)
+    }
+
+    /// Returns the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdxRandomAccessReader;
+    use super::IdxRecordReader;
+    use crate::ErrorKind;
+    use crate::IdxArray;
+    use crate::IdxHeader;
+    use crate::ReadError;
+    use std::fs;
+    use std::io;
+    use std::io::Cursor;
+    use std::io::Read;
+
+    /// Reads one byte at a time, failing once when it reaches `fail_at`.
+    struct FailOnce<'a> {
+        bytes: &'a [u8],
+        pos: usize,
+        fail_at: Option<usize>,
+    }
+
+    impl Read for FailOnce<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            if self.fail_at == Some(self.pos) {
+                self.fail_at = None;
+                return Err(io::Error::other("boom"));
+            }
+            let n = buf.len().min(1).min(self.bytes.len() - self.pos);
+            buf[..n].copy_from_slice(&self.bytes[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_records_match_parse() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let (_, data) = IdxArray::<u8, 3>::parse(&x)
+            .expect("parse index")
+            .dims_data();
+        let records = IdxRecordReader::<u8, _>::new(&x[..]).expect("read header");
+        assert_eq!(records.record_dims(), [28, 28]);
+        assert_eq!(records.len(), 10_000);
+        let records: Vec<_> = records.map(|r| r.expect("read record")).collect();
+        assert!(records
+            .iter()
+            .map(Vec::as_slice)
+            .eq(data.chunks_exact(28 * 28)));
+    }
+
+    #[test]
+    fn test_truncated_record() {
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let mut records = IdxRecordReader::<u8, _>::new(&x[..9]).expect("read header");
+        let mut record = Vec::new();
+        assert!(records.read_record(&mut record).expect("read record"));
+        assert_eq!(records.len(), 9_999);
+        match records.read_record(&mut record) {
+            Err(ReadError::Parse(e)) => {
+                let kind = ErrorKind::Truncated {
+                    expected: 1,
+                    found: 0,
+                };
+                assert_eq!((e.offset(), e.kind()), (9, &kind));
+            }
+            other => panic!("expected parse error, got {other:?}"),
+        }
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_record_read_error() {
+        let x = IdxArray::from_dims_data([3, 2], vec![10u8, 11, 20, 21, 30, 31])
+            .expect("array")
+            .to_bytes();
+        let reader = FailOnce {
+            bytes: &x,
+            pos: 0,
+            fail_at: Some(13),
+        };
+        let mut records = IdxRecordReader::<u8, _>::new(reader).expect("read header");
+        match records.next() {
+            Some(Err(ReadError::Io(e))) => assert_eq!(e.to_string(), "boom"),
+            other => panic!("expected io error, got {other:?}"),
+        }
+        assert_eq!(records.remaining(), 0);
+        assert!(records.next().is_none());
+    }
+
+    #[test]
+    fn test_oversized_records() {
+        // No records, each of which would be too large to address.
+        let x = [&[0, 0, 0x08, 4, 0, 0, 0, 0][..], &[0xFF; 12]].concat();
+        let kind = ErrorKind::DimsOverflow {
+            dims: vec![0, u32::MAX, u32::MAX, u32::MAX],
+        };
+        assert_eq!(IdxHeader::parse(&x).unwrap_err().kind(), &kind);
+        assert_eq!(IdxArray::<u8, 4>::parse(&x).unwrap_err().kind(), &kind);
+        match IdxRecordReader::<u8, _>::new(&x[..]) {
+            Err(ReadError::Parse(e)) => assert_eq!((e.offset(), e.kind()), (4, &kind)),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+
+        // Records of 16 GiB, which are only allocated for as the file holds them.
+        let header = IdxArray::<u8, 3>::from_dims_data([0, 1 << 30, 16], vec![])
+            .expect("array")
+            .to_bytes();
+        let mut records = IdxRecordReader::<u8, _>::new(&header[..]).expect("read header");
+        assert!(records.next().is_none());
+        let x = [&header[..4], &[0, 0, 0, 1], &header[8..], &[7; 100]].concat();
+        let mut records = IdxRecordReader::<u8, _>::new(&x[..]).expect("read header");
+        match records.next() {
+            Some(Err(ReadError::Parse(e))) => {
+                let kind = ErrorKind::Truncated {
+                    expected: 1 << 34,
+                    found: 100,
+                };
+                assert_eq!((e.offset(), e.kind()), (16, &kind));
+            }
+            other => panic!("expected parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_random_access_matches_parse() {
+        let x = fs::read("data/t10k-labels.idx1-ubyte").expect("idx file");
+        let labels = IdxArray::<u8, 1>::parse(&x)
+            .expect("parse index")
+            .into_sequence();
+        let path = "data/t10k-labels.idx1-ubyte";
+        let mut records = IdxRandomAccessReader::<u8, _>::open(path).expect("read header");
+        assert_eq!(records.len(), 10_000);
+        for i in [9_999, 0, 4_321, 17] {
+            assert_eq!(records.record(i).expect("read record"), [labels[i]]);
+        }
+
+        let mut records =
+            IdxRandomAccessReader::<u8, _>::new(Cursor::new(&x[..100])).expect("read header");
+        assert_eq!(records.record(91).expect("read record"), [labels[91]]);
+        match records.record(92) {
+            Err(ReadError::Parse(e)) => assert_eq!(e.offset(), 100),
+            other => panic!("expected parse error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_random_access_oversized_records() {
+        let x = [&[0, 0, 0x08, 4, 0, 0, 0, 0][..], &[0xFF; 12]].concat();
+        match IdxRandomAccessReader::<u8, _>::new(Cursor::new(&x)) {
+            Err(ReadError::Parse(e)) => {
+                assert!(matches!(e.kind(), ErrorKind::DimsOverflow { .. }));
+            }
+            other => panic!("expected parse error, got {other:?}"),
+        }
+
+        let header = IdxArray::<u8, 3>::from_dims_data([0, 1 << 30, 16], vec![])
+            .expect("array")
+            .to_bytes();
+        let records = IdxRandomAccessReader::<u8, _>::new(Cursor::new(&header)).expect("header");
+        assert!(records.is_empty());
+        let x = [&header[..4], &[0, 0, 0, 2], &header[8..], &[7; 100]].concat();
+        let mut records = IdxRandomAccessReader::<u8, _>::new(Cursor::new(&x)).expect("header");
+        match records.record(1) {
+            Err(ReadError::Parse(e)) => {
+                let kind = ErrorKind::Truncated {
+                    expected: 1 << 34,
+                    found: 0,
+                };
+                assert_eq!((e.offset(), e.kind()), (16 + (1 << 34), &kind));
+            }
+            other => panic!("expected parse error, got {other:?}"),
+        }
+    }
+}
@@ -0,0 +1,216 @@
+//! Borrowed views of `IDX` files that decode elements in place instead of copying them out.
+
+use crate::parse_typed_header;
+use crate::run_parser;
+use crate::take_payload;
+use crate::DataFormat;
+use crate::Error;
+use crate::HResult;
+use crate::IdxArray;
+use core::marker::PhantomData;
+use core::mem;
+use core::slice;
+
+#[cfg(feature = "mmap")]
+use crate::IdxHeader;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+#[cfg(feature = "mmap")]
+use std::fs::File;
+#[cfg(feature = "mmap")]
+use std::io;
+#[cfg(feature = "mmap")]
+use std::path::Path;
+
+fn parse_view<T: DataFormat, const N: usize>(x: &[u8]) -> HResult<'_, ([u32; N], &[u8])> {
+    let (x, (dims, elements)) = parse_typed_header::<T, N>(x)?;
+    let (x, payload) = take_payload::<T>(x, elements)?;
+    Ok((x, (dims, payload)))
+}
+
+/// An `IDX` file viewed in place, borrowing the raw contents rather than decoding them up
+/// front.
+///
+/// For `u8` and `i8` the payload already is the elements, so [`IdxView::as_slice`] is
+/// zero-copy. Multi-byte elements are stored big-endian, and are decoded on access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct IdxView<'a, T, const N: usize> {
+    dims: [u32; N],
+    payload: &'a [u8],
+    element: PhantomData<T>,
+}
+
+impl<'a, T: DataFormat, const N: usize> IdxView<'a, T, N> {
+    /// Views `input`, which is the raw contents of an `IDX` file.
+    ///
+    /// Checks the same things as [`IdxArray::parse`], but unlike it cannot view compressed
+    /// input, which fails as not starting with the zero prefix.
+    pub fn parse(input: &'a [u8]) -> Result<Self, Error> {
+        let (dims, payload) = run_parser(input, parse_view::<T, N>)?;
+        Ok(IdxView {
+            dims,
+            payload,
+            element: PhantomData,
+        })
+    }
+
+    /// Returns the length of each axis of the array.
+    pub fn dims(&self) -> [u32; N] {
+        self.dims
+    }
+
+    /// Returns the number of elements in the array.
+    pub fn len(&self) -> usize {
+        self.payload.len() / mem::size_of::<T>()
+    }
+
+    /// Returns `true` if the array has no elements.
+    pub fn is_empty(&self) -> bool {
+        self.payload.is_empty()
+    }
+
+    /// Returns the raw big-endian bytes of the elements.
+    pub fn payload(&self) -> &'a [u8] {
+        self.payload
+    }
+
+    /// Decodes the element at `index` of the flattened array, if it is in bounds.
+    pub fn get(&self, index: usize) -> Option<T> {
+        if index >= self.len() {
+            return None;
+        }
+        let size = mem::size_of::<T>();
+        let bytes = &self.payload[index * size..(index + 1) * size];
+        Some(T::READ_ELEMENT(bytes).expect("element size").1)
+    }
+
+    /// Decodes the elements of the flattened array in order.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = T> + 'a {
+        let elements = self.payload.chunks_exact(mem::size_of::<T>());
+        elements.map(|x| T::READ_ELEMENT(x).expect("element size").1)
+    }
+
+    /// Decodes the whole array into an owned [`IdxArray`].
+    pub fn to_array(&self) -> IdxArray<T, N> {
+        IdxArray {
+            dims: self.dims,
+            data: self.iter().collect(),
+        }
+    }
+}
+
+impl<'a, const N: usize> IdxView<'a, u8, N> {
+    /// Returns the elements of the flattened array, without copying.
+    pub fn as_slice(&self) -> &'a [u8] {
+        self.payload
+    }
+}
+
+impl<'a, const N: usize> IdxView<'a, i8, N> {
+    /// Returns the elements of the flattened array, without copying.
+    pub fn as_slice(&self) -> &'a [i8] {
+        // SAFETY: `i8` has the same size and alignment as `u8`, and every bit pattern is valid.
+        unsafe { slice::from_raw_parts(self.payload.as_ptr().cast(), self.payload.len()) }
+    }
+}
+
+/// An `IDX` file mapped into memory, from which [`IdxView`]s can be taken without reading the
+/// file up front.
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub struct IdxMmap {
+    mmap: Mmap,
+}
+
+#[cfg(feature = "mmap")]
+impl IdxMmap {
+    /// Maps the file at `path` into memory.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be modified or truncated while it is mapped, as with
+    /// [`Mmap::map`].
+    pub unsafe fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        let mmap = Mmap::map(&file)?;
+        Ok(IdxMmap { mmap })
+    }
+
+    /// Returns the raw contents of the file.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.mmap
+    }
+
+    /// Parses the header of the file.
+    pub fn header(&self) -> Result<IdxHeader, Error> {
+        IdxHeader::parse(&self.mmap)
+    }
+
+    /// Views the contents of the file as an array of `T` with rank `N`.
+    pub fn view<T: DataFormat, const N: usize>(&self) -> Result<IdxView<'_, T, N>, Error> {
+        IdxView::parse(&self.mmap)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::IdxView;
+    use crate::ErrorKind;
+    use crate::IdxArray;
+    use std::fs;
+
+    #[test]
+    fn test_view_matches_parse() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let array = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        let view = IdxView::<u8, 3>::parse(&x).expect("view index");
+        assert_eq!(view.dims(), [10_000, 28, 28]);
+        assert_eq!(view.as_slice().as_ptr(), x[16..].as_ptr());
+        assert_eq!(view.to_array(), array);
+
+        let e = IdxView::<u8, 3>::parse(&x[..x.len() - 1]).unwrap_err();
+        assert_eq!(e, IdxArray::<u8, 3>::parse(&x[..x.len() - 1]).unwrap_err());
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn test_view_rejects_compressed() {
+        let array = IdxArray::from_dims_data([2], vec![1u8, 2]).expect("array");
+        let gz = array.write_gzip_to(Vec::new()).expect("compress");
+        assert_eq!(IdxArray::parse(&gz), Ok(array));
+        let e = IdxView::<u8, 1>::parse(&gz).unwrap_err();
+        assert_eq!(e.kind(), &ErrorKind::ZeroPrefix { found: 0x1F8B });
+    }
+
+    #[test]
+    fn test_view_decodes_multi_byte_elements() {
+        let x = [0, 0, 0x0B, 1, 0, 0, 0, 2, 0xFF, 0xFE, 0x01, 0x00];
+        let view = IdxView::<i16, 1>::parse(&x).expect("view index");
+        assert_eq!(view.len(), 2);
+        assert_eq!(
+            (view.get(0), view.get(1), view.get(2)),
+            (Some(-2), Some(256), None)
+        );
+        assert_eq!(view.get(usize::MAX), None);
+        assert!(view.iter().eq([-2, 256]));
+
+        let e = IdxView::<i8, 1>::parse(&x).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::MagicByte { .. }));
+        let x = [0, 0, 0x09, 1, 0, 0, 0, 2, 0xFF, 0x01];
+        assert_eq!(
+            IdxView::<i8, 1>::parse(&x).expect("view index").as_slice(),
+            [-1, 1]
+        );
+    }
+
+    #[cfg(feature = "mmap")]
+    #[test]
+    fn test_mmap() {
+        // SAFETY: nothing modifies the bundled test data.
+        let mmap =
+            unsafe { super::IdxMmap::open("data/t10k-labels.idx1-ubyte") }.expect("map file");
+        let view = mmap.view::<u8, 1>().expect("view index");
+        assert_eq!(view.len(), 10_000);
+        assert_eq!(mmap.header().expect("parse header").dims(), [10_000]);
+    }
+}
@@ -0,0 +1,298 @@
+//! Writing arrays out in the `IDX` format.
+
+use crate::DataFormat;
+use crate::IdxArray;
+use alloc::vec::Vec;
+use core::mem;
+
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::io::Seek;
+#[cfg(feature = "std")]
+use std::io::SeekFrom;
+#[cfg(feature = "std")]
+use std::io::Write;
+#[cfg(feature = "std")]
+use std::marker::PhantomData;
+
+/// Number of payload bytes encoded at a time.
+#[cfg(feature = "std")]
+const CHUNK_LEN: usize = 1 << 16;
+
+/// Appends the header for an array of `T` with the given `dims` to `out`.
+pub(crate) fn encode_header<T: DataFormat>(dims: &[u32], out: &mut Vec<u8>) {
+    let num_dims = u8::try_from(dims.len()).expect("rank fits in a header");
+    out.extend([0, 0, T::MAGIC_BYTE, num_dims]);
+    out.extend(dims.iter().flat_map(|dim| dim.to_be_bytes()));
+}
+
+/// Appends the big-endian encoding of `data` to `out`.
+pub(crate) fn encode_elements<T: DataFormat>(data: &[T], out: &mut Vec<u8>) {
+    for x in data {
+        T::WRITE_ELEMENT(x, out);
+    }
+}
+
+/// Writes the big-endian encoding of `data` to `writer`, a chunk at a time.
+#[cfg(feature = "std")]
+pub(crate) fn write_elements<T: DataFormat>(writer: &mut impl Write, data: &[T]) -> io::Result<()> {
+    let mut buf = Vec::with_capacity(CHUNK_LEN);
+    for chunk in data.chunks(CHUNK_LEN / mem::size_of::<T>()) {
+        buf.clear();
+        encode_elements(chunk, &mut buf);
+        writer.write_all(&buf)?;
+    }
+    Ok(())
+}
+
+impl<T: DataFormat, const N: usize> IdxArray<T, N> {
+    /// Writes the array to `writer` in the `IDX` format, such that [`IdxArray::parse`] reads
+    /// back the same array.
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let mut header = Vec::new();
+        encode_header::<T>(&self.dims, &mut header);
+        writer.write_all(&header)?;
+        write_elements(&mut writer, &self.data)
+    }
+
+    /// Returns the contents of an `IDX` file holding the array.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(4 + 4 * N + self.data.len() * mem::size_of::<T>());
+        encode_header::<T>(&self.dims, &mut out);
+        encode_elements(&self.data, &mut out);
+        out
+    }
+}
+
+/// How the header of an [`IdxWriter`] gets its final number of records.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+enum Records<W> {
+    /// Filled in by `patch` seeking back to the header, which starts at `start`.
+    Patched {
+        start: u64,
+        patch: fn(&mut W, u64, u32) -> io::Result<()>,
+    },
+    /// Declared up front, and checked against the number actually written.
+    Declared(u32),
+}
+
+#[cfg(feature = "std")]
+fn patch_records<W: Write + Seek>(writer: &mut W, start: u64, records: u32) -> io::Result<()> {
+    let end = writer.stream_position()?;
+    writer.seek(SeekFrom::Start(start + 4))?;
+    writer.write_all(&records.to_be_bytes())?;
+    writer.seek(SeekFrom::Start(end))?;
+    Ok(())
+}
+
+/// Writes an `IDX` file one record at a time.
+///
+/// Made with [`IdxWriter::new`], a provisional header claiming no records is written straight
+/// away, and its first dimension is filled in by [`IdxWriter::finish`], for when the number of
+/// records is not known up front. Made with [`IdxWriter::with_len`], the number of records is
+/// declared up front instead, so that `writer` need not be seekable.
+///
+/// Each record is written as it arrives, so wrap `writer` in a
+/// [`BufWriter`](std::io::BufWriter) when records are small.
+#[cfg(feature = "std")]
+#[derive(Debug)]
+pub struct IdxWriter<W, T, const N: usize> {
+    writer: W,
+    records: Records<W>,
+    record_len: usize,
+    written: u32,
+    buf: Vec<u8>,
+    element: PhantomData<T>,
+}
+
+#[cfg(feature = "std")]
+impl<W: Write + Seek, T: DataFormat, const N: usize> IdxWriter<W, T, N> {
+    /// Writes a provisional header at the current position of `writer`, for records with
+    /// dimensions `record_dims`.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `record_dims` has `N - 1` entries and `N` fits in an `IDX` header.
+    pub fn new(mut writer: W, record_dims: &[u32]) -> io::Result<Self> {
+        let start = writer.stream_position()?;
+        let patch = patch_records::<W>;
+        Self::start(writer, Records::Patched { start, patch }, record_dims)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<W: Write, T: DataFormat, const N: usize> IdxWriter<W, T, N> {
+    /// Writes the header for `records` records with dimensions `record_dims` to `writer`.
+    ///
+    /// Since the header is final, `writer` can be a compressing encoder, such as those of the
+    /// `flate2` and `zstd` crates.
+    ///
+    /// # Panics
+    ///
+    /// Panics unless `record_dims` has `N - 1` entries and `N` fits in an `IDX` header.
+    pub fn with_len(writer: W, records: u32, record_dims: &[u32]) -> io::Result<Self> {
+        Self::start(writer, Records::Declared(records), record_dims)
+    }
+
+    fn start(mut writer: W, records: Records<W>, record_dims: &[u32]) -> io::Result<Self> {
+        assert_eq!(record_dims.len() + 1, N, "records must have rank N - 1");
+        assert!(
+            N <= usize::from(u8::MAX),
+            "rank too large for an IDX header"
+        );
+        let record_len = record_dims
+            .iter()
+            .try_fold(1usize, |a, &b| a.checked_mul(usize::try_from(b).ok()?))
+            .filter(|record_len| record_len.checked_mul(mem::size_of::<T>()).is_some())
+            .ok_or_else(|| {
+                let msg = format!("record dimensions {record_dims:?} are too large to address");
+                io::Error::new(io::ErrorKind::InvalidInput, msg)
+            })?;
+        let first_dim = match records {
+            Records::Patched { .. } => 0,
+            Records::Declared(records) => records,
+        };
+        let mut header = Vec::with_capacity(4 + 4 * N);
+        encode_header::<T>(&[&[first_dim], record_dims].concat(), &mut header);
+        writer.write_all(&header)?;
+        Ok(IdxWriter {
+            writer,
+            records,
+            record_len,
+            written: 0,
+            buf: Vec::new(),
+            element: PhantomData,
+        })
+    }
+
+    /// Returns the number of records written so far.
+    pub fn records(&self) -> u32 {
+        self.written
+    }
+
+    /// Appends `record`, which must have as many elements as the record dimensions call for.
+    pub fn write_record(&mut self, record: &[T]) -> io::Result<()> {
+        if record.len() != self.record_len {
+            let msg = format!(
+                "record has {} elements, expected {}",
+                record.len(),
+                self.record_len
+            );
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        let limit = match self.records {
+            Records::Patched { .. } => u32::MAX,
+            Records::Declared(records) => records,
+        };
+        if self.written == limit {
+            let msg = format!("more than the {limit} records the header allows");
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+        }
+        self.buf.clear();
+        encode_elements(record, &mut self.buf);
+        self.writer.write_all(&self.buf)?;
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Completes the header, and returns the underlying writer positioned at the end of the
+    /// file.
+    ///
+    /// Fails if fewer records were written than were declared with [`IdxWriter::with_len`].
+    pub fn finish(mut self) -> io::Result<W> {
+        match self.records {
+            Records::Patched { start, patch } => patch(&mut self.writer, start, self.written)?,
+            Records::Declared(records) if records != self.written => {
+                let msg = format!("wrote {} of {records} declared records", self.written);
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, msg));
+            }
+            Records::Declared(_) => {}
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::IdxWriter;
+    use crate::DataFormat;
+    use crate::IdxArray;
+    use std::fmt::Debug;
+    use std::fs;
+    use std::io;
+    use std::io::Cursor;
+
+    fn round_trip<T: DataFormat + Clone + Debug + PartialEq, const N: usize>(
+        dims: [u32; N],
+        data: Vec<T>,
+    ) {
+        let array = IdxArray::from_dims_data(dims, data).expect("element count");
+        let bytes = array.to_bytes();
+        let mut written = Vec::new();
+        array.write_to(&mut written).expect("write index");
+        assert_eq!(bytes, written);
+        assert_eq!(IdxArray::parse(&bytes), Ok(array));
+    }
+
+    #[test]
+    fn test_round_trip() {
+        round_trip([2, 2], vec![0u8, 1, 254, 255]);
+        round_trip([3], vec![i8::MIN, 0, i8::MAX]);
+        round_trip([1, 3], vec![i16::MIN, -1, i16::MAX]);
+        round_trip([3, 1, 1], vec![i32::MIN, 12_345_678, i32::MAX]);
+        round_trip([2], vec![-0.5f32, f32::INFINITY]);
+        round_trip([2, 0], Vec::<f64>::new());
+        round_trip(
+            [4],
+            vec![-0.0f64, f64::MIN_POSITIVE, 1e300, f64::NEG_INFINITY],
+        );
+    }
+
+    #[test]
+    fn test_bytes_match_file() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let array = IdxArray::<u8, 3>::parse(&x).expect("parse index");
+        assert!(array.to_bytes() == x);
+        assert!(IdxArray::from_dims_data([2, 2], vec![0u8; 3]).is_none());
+    }
+
+    #[test]
+    fn test_idx_writer() {
+        let x = fs::read("data/t10k-images.idx3-ubyte").expect("idx file");
+        let (_, data) = IdxArray::<u8, 3>::parse(&x)
+            .expect("parse index")
+            .dims_data();
+        let mut writer =
+            IdxWriter::<_, u8, 3>::new(Cursor::new(Vec::new()), &[28, 28]).expect("write header");
+        for record in data.chunks_exact(28 * 28) {
+            writer.write_record(record).expect("write record");
+        }
+        let e = writer.write_record(&[0; 27 * 28]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        assert_eq!(writer.records(), 10_000);
+        let written = writer.finish().expect("finish").into_inner();
+        assert!(written == x);
+    }
+
+    #[test]
+    fn test_idx_writer_with_len() {
+        let mut writer = IdxWriter::<_, i16, 2>::with_len(Vec::new(), 2, &[1]).expect("header");
+        writer.write_record(&[-1]).expect("write record");
+        writer.write_record(&[2]).expect("write record");
+        let e = writer.write_record(&[3]).unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidInput);
+        let written = writer.finish().expect("finish");
+        let array = IdxArray::from_dims_data([2, 1], vec![-1i16, 2]).expect("element count");
+        assert_eq!(written, array.to_bytes());
+
+        let writer = IdxWriter::<_, i16, 2>::with_len(Vec::new(), 2, &[1]).expect("header");
+        assert_eq!(
+            writer.finish().unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}